@@ -0,0 +1,72 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use rlox_core::{Chunk, LoxVm, OpCode, VerifiedChunk};
+
+/// Straight line code that keeps the stack shallow, so dispatch dominates.
+fn arithmetic_chunk(rounds: usize) -> Chunk {
+    let mut chunk = Chunk::new();
+    let one = chunk.add_constant(1.0);
+    let two = chunk.add_constant(2.0);
+
+    chunk.write(OpCode::Constant, 1);
+    chunk.write(one, 1);
+    for _ in 0..rounds {
+        chunk.write(OpCode::Constant, 2);
+        chunk.write(two, 2);
+        chunk.write(OpCode::Multiply, 2);
+        chunk.write(OpCode::Constant, 3);
+        chunk.write(one, 3);
+        chunk.write(OpCode::Add, 3);
+        chunk.write(OpCode::Negate, 3);
+    }
+    chunk.write(OpCode::Return, 4);
+
+    chunk
+}
+
+/// Long constant indices exercise multi-byte operand fetches.
+fn constant_long_chunk(rounds: usize) -> Chunk {
+    let mut chunk = Chunk::new();
+    let one = chunk.add_constant_long(1.0);
+
+    chunk.write(OpCode::ConstantLong, 1);
+    chunk.write(one, 1);
+    for _ in 0..rounds {
+        chunk.write(OpCode::ConstantLong, 2);
+        chunk.write(one, 2);
+        chunk.write(OpCode::Subtract, 2);
+    }
+    chunk.write(OpCode::Return, 3);
+
+    chunk
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut vm = LoxVm::new();
+
+    for (name, chunk) in [
+        ("arithmetic", arithmetic_chunk(10_000)),
+        ("constant long", constant_long_chunk(10_000)),
+    ] {
+        // Execution only, with the chunk verified once up front.
+        let verified = VerifiedChunk::new(chunk.clone()).unwrap();
+        c.bench_function(&format!("dispatch {}", name), |b| {
+            b.iter_batched(
+                || verified.clone(),
+                |chunk| black_box(vm.interpret_verified(chunk)),
+                BatchSize::LargeInput,
+            )
+        });
+
+        // Verification and execution.
+        c.bench_function(&format!("interpret {}", name), |b| {
+            b.iter_batched(
+                || chunk.clone(),
+                |chunk| black_box(vm.interpret(chunk)),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
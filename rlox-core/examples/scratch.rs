@@ -1,4 +1,3 @@
-use flame;
 use rlox_core::{Chunk, LoxVm, OpCode};
 use std::fs::File;
 
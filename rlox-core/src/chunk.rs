@@ -3,6 +3,7 @@ use crate::{opcode::OpCode, value::Value};
 use num_traits::{FromPrimitive, ToPrimitive};
 use std::fmt::Write as FmtWrite;
 
+#[derive(Clone)]
 pub struct Chunk {
     constants: Vec<Value>,
     code: Vec<u8>,
@@ -34,12 +35,54 @@ impl Chunk {
         self.code[offset]
     }
 
+    /// Retrieve a byte instruction from the chunk code, without bounds checking.
+    ///
+    /// # Safety
+    ///
+    /// The offset must be less than the length of the chunk code.
+    #[inline(always)]
+    pub(crate) unsafe fn get_byte_unchecked(&self, offset: usize) -> u8 {
+        *self.code.get_unchecked(offset)
+    }
+
+    /// Returns the chunk code.
+    #[inline]
+    pub(crate) fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    /// Returns the source line of the instruction at the given offset.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the given offset is out of bounds.
+    #[inline]
+    pub fn get_line(&self, offset: usize) -> usize {
+        self.line[offset]
+    }
+
+    /// Returns the source line of the last instruction, or 0 when the chunk is empty.
+    pub(crate) fn last_line(&self) -> usize {
+        self.line.last().copied().unwrap_or(0)
+    }
+
     /// Returns the number of instructions in the chunk code.
     #[inline(always)]
     pub fn len(&self) -> usize {
         self.code.len()
     }
 
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+
+    /// Returns the number of constants in the chunk's constant registry.
+    #[inline]
+    pub fn constant_count(&self) -> usize {
+        self.constants.len()
+    }
+
     /// Adds a constant value to the chunk's constant registry.
     ///
     /// Returns a unique index that can be used to reference the constant.
@@ -205,6 +248,12 @@ impl Chunk {
     }
 }
 
+impl Default for Chunk {
+    fn default() -> Self {
+        Chunk::new()
+    }
+}
+
 /// Unique identifier to a constant value stored in a chunk.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum ConstantIndex {
@@ -0,0 +1,26 @@
+//! Virtual machine configuration.
+
+/// Limits for a [`LoxVm`](struct.LoxVm.html).
+#[derive(Debug, Clone)]
+pub struct VmConfig {
+    /// Number of values the stack has room for before it needs to grow.
+    pub initial_stack: usize,
+    /// Maximum number of values on the stack. Pushing past it is a
+    /// `LoxError::StackOverflow` error.
+    pub max_stack: usize,
+    /// Maximum depth of nested call frames.
+    pub max_frames: usize,
+    /// Maximum number of constants in a chunk the VM will accept.
+    pub max_constants: usize,
+}
+
+impl Default for VmConfig {
+    fn default() -> Self {
+        VmConfig {
+            initial_stack: 256,
+            max_stack: 64 * 1024,
+            max_frames: 256,
+            max_constants: 1 << 24,
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Conversions between Rust and Lox values, for host code driving the VM.
+use crate::{error::LoxError, value::Value};
+use std::{error::Error, fmt};
+
+/// Conversion from a Lox value into a Rust type.
+pub trait FromLox: Sized {
+    fn from_lox(value: Value) -> Result<Self, ConversionError>;
+}
+
+/// Conversion from a Rust type into a Lox value.
+pub trait IntoLox {
+    fn into_lox(self) -> Value;
+}
+
+/// A Lox value did not have the type a host expected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionError {
+    /// Lox type the host asked for.
+    pub expected: &'static str,
+    /// Lox type of the value that was found.
+    pub found: &'static str,
+}
+
+impl ConversionError {
+    fn new(expected: &'static str, value: &Value) -> Self {
+        ConversionError {
+            expected,
+            found: type_name(value),
+        }
+    }
+}
+
+impl Error for ConversionError {}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {}, found {}", self.expected, self.found)
+    }
+}
+
+impl From<ConversionError> for LoxError {
+    fn from(_: ConversionError) -> Self {
+        LoxError::TypeError
+    }
+}
+
+/// Returns the name of the value's Lox type, as used in conversion errors.
+pub fn type_name(value: &Value) -> &'static str {
+    if value.is_null() {
+        "null"
+    } else if value.is_err() {
+        "error"
+    } else {
+        "number"
+    }
+}
+
+impl FromLox for Value {
+    fn from_lox(value: Value) -> Result<Self, ConversionError> {
+        Ok(value)
+    }
+}
+
+impl IntoLox for Value {
+    fn into_lox(self) -> Value {
+        self
+    }
+}
+
+impl FromLox for f64 {
+    fn from_lox(value: Value) -> Result<Self, ConversionError> {
+        value.as_f64().ok_or_else(|| ConversionError::new("number", &value))
+    }
+}
+
+impl IntoLox for f64 {
+    fn into_lox(self) -> Value {
+        Value::from(self)
+    }
+}
+
+impl FromLox for () {
+    fn from_lox(value: Value) -> Result<Self, ConversionError> {
+        if value.is_null() {
+            Ok(())
+        } else {
+            Err(ConversionError::new("null", &value))
+        }
+    }
+}
+
+impl IntoLox for () {
+    fn into_lox(self) -> Value {
+        Value::default()
+    }
+}
+
+/// `null` converts to `None`, anything else must convert to `T`.
+impl<T: FromLox> FromLox for Option<T> {
+    fn from_lox(value: Value) -> Result<Self, ConversionError> {
+        if value.is_null() {
+            Ok(None)
+        } else {
+            T::from_lox(value).map(Some)
+        }
+    }
+}
+
+impl<T: IntoLox> IntoLox for Option<T> {
+    fn into_lox(self) -> Value {
+        match self {
+            Some(value) => value.into_lox(),
+            None => Value::default(),
+        }
+    }
+}
@@ -1,13 +1,31 @@
 //! Errors
+use rlox_gc::AllocError;
 use std::{error::Error, fmt};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LoxError {
     /// Error during script compilation.
     Compile,
     /// Error during script execution.
     Runtime,
     TypeError,
+    /// Allocation would exceed the heap limit, even after a full collection.
+    ///
+    /// Values don't live on the collector's heap yet, so the VM never allocates
+    /// itself. Host code allocating with `Collector::try_alloc` gets this error
+    /// through `?`, from the collector's `AllocError`.
+    OutOfMemory,
+    /// Value stack grew past `VmConfig::max_stack`.
+    StackOverflow,
+    /// Execution ran out of fuel, or was stopped by an interrupt handle.
+    /// The VM can continue with `LoxVm::resume`.
+    Interrupted,
+    /// Execution reached a breakpoint, or finished a step.
+    /// The VM can continue with `LoxVm::resume` or `LoxVm::step`.
+    Paused,
+    /// `LoxVm::resume` or `LoxVm::step` was called when the last run had not been
+    /// interrupted or paused.
+    NotResumable,
 }
 
 impl Error for LoxError {}
@@ -18,8 +36,19 @@ impl fmt::Display for LoxError {
             LoxError::Compile => write!(f, "compilation error"),
             LoxError::Runtime => write!(f, "runtime error"),
             LoxError::TypeError => write!(f, "type error"),
+            LoxError::OutOfMemory => write!(f, "out of memory"),
+            LoxError::StackOverflow => write!(f, "stack overflow"),
+            LoxError::Interrupted => write!(f, "execution interrupted"),
+            LoxError::Paused => write!(f, "execution paused"),
+            LoxError::NotResumable => write!(f, "execution cannot be resumed"),
         }
     }
 }
 
+impl From<AllocError> for LoxError {
+    fn from(_: AllocError) -> Self {
+        LoxError::OutOfMemory
+    }
+}
+
 pub type Result<T> = std::result::Result<T, LoxError>;
@@ -0,0 +1,29 @@
+//! Stopping a running virtual machine.
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Thread-safe handle used to interrupt a [`LoxVm`](struct.LoxVm.html).
+///
+/// The VM checks for an interrupt between instructions, and returns
+/// [`LoxError::Interrupted`](enum.LoxError.html#variant.Interrupted).
+/// Execution can be continued with [`LoxVm::resume`](struct.LoxVm.html#method.resume).
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle {
+    flag: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    /// Requests the VM to stop at the next check.
+    pub fn interrupt(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    /// Clears a pending interrupt, returning whether one was requested.
+    #[inline(always)]
+    pub(crate) fn take(&self) -> bool {
+        // Plain load first, so the common case doesn't write to the shared cache line.
+        self.flag.load(Ordering::Relaxed) && self.flag.swap(false, Ordering::Relaxed)
+    }
+}
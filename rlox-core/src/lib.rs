@@ -1,14 +1,25 @@
 //! Core `rlox` compiler and virtual machine.
 mod chunk;
+mod config;
+mod convert;
 mod error;
+mod interrupt;
 mod opcode;
+mod optimize;
+pub mod trace;
 mod value;
+mod verify;
 mod vm;
 
 pub use self::chunk::{Chunk, ConstantIndex};
+pub use self::config::VmConfig;
+pub use self::convert::{type_name, ConversionError, FromLox, IntoLox};
 pub use self::error::{LoxError, Result};
+pub use self::interrupt::InterruptHandle;
 pub use self::opcode::OpCode;
+pub use self::optimize::OptLevel;
 pub use self::value::Value;
+pub use self::verify::VerifiedChunk;
 pub use self::vm::LoxVm;
 
 pub mod prelude {
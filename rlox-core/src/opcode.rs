@@ -5,9 +5,6 @@ use num_derive::{FromPrimitive, ToPrimitive};
 #[repr(u8)]
 pub enum OpCode {
     /// Only used by the VM to advance the instruction pointer.
-    ///
-    /// In the future this will be used to convert u8 to `OpCode` without having to unwrap from `Option`. Unwrapping
-    /// introduces additional overhead in the VM loop.
     NoOp = 0,
     /// Return from a function.
     Return = 1,
@@ -28,3 +25,45 @@ pub enum OpCode {
     Multiply,
     Divide,
 }
+
+impl OpCode {
+    /// Number of opcodes. Every byte below this value is a valid opcode.
+    pub(crate) const COUNT: u8 = OpCode::Divide as u8 + 1;
+
+    /// Converts a byte to an opcode without checking that it's valid.
+    ///
+    /// Avoids unwrapping an `Option` from `FromPrimitive` in the VM loop.
+    ///
+    /// # Safety
+    ///
+    /// The byte must be less than `OpCode::COUNT`. This holds for every opcode
+    /// in a verified chunk.
+    #[inline(always)]
+    pub(crate) unsafe fn from_u8_unchecked(byte: u8) -> OpCode {
+        debug_assert!(byte < Self::COUNT, "invalid opcode {:x}", byte);
+        std::mem::transmute(byte)
+    }
+
+    /// Number of operand bytes that follow the opcode.
+    #[inline]
+    pub fn operand_len(self) -> usize {
+        match self {
+            OpCode::Constant => 1,
+            OpCode::ConstantLong => 3,
+            _ => 0,
+        }
+    }
+
+    /// Number of values the instruction pops from the stack, and the number it pushes.
+    ///
+    /// `Return` pops its result if there is one, and returns null otherwise.
+    #[inline]
+    pub(crate) fn stack_effect(self) -> (usize, usize) {
+        match self {
+            OpCode::NoOp | OpCode::Return => (0, 0),
+            OpCode::Constant | OpCode::ConstantLong => (0, 1),
+            OpCode::Negate => (1, 1),
+            OpCode::Add | OpCode::Subtract | OpCode::Multiply | OpCode::Divide => (2, 1),
+        }
+    }
+}
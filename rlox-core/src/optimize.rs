@@ -0,0 +1,139 @@
+//! Bytecode optimizer.
+//!
+//! The optimizer decodes a chunk into a list of instructions, rewrites the
+//! list, and emits a new chunk with its own constant table and line table.
+//!
+//! Chunks don't contain jumps yet, so instructions always run in the order they
+//! appear, and neighbouring instructions can be rewritten freely.
+use crate::{
+    chunk::{Chunk, ConstantIndex},
+    opcode::OpCode,
+    value::Value,
+};
+use num_traits::FromPrimitive;
+
+/// How much effort the optimizer spends on a chunk, like the `-O` flag of a compiler.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    /// The chunk is left as is.
+    #[default]
+    O0,
+    /// Constant folding, and removal of `NoOp` instructions.
+    O1,
+}
+
+/// Instruction with its operand resolved.
+#[derive(Debug, Clone, Copy)]
+enum Instr {
+    Constant(Value),
+    Op(OpCode),
+}
+
+impl Chunk {
+    /// Returns an optimized version of the chunk.
+    ///
+    /// Malformed chunks are returned unchanged, and left for the VM to reject.
+    pub fn optimize(self, level: OptLevel) -> Chunk {
+        if level == OptLevel::O0 {
+            return self;
+        }
+
+        match decode(&self) {
+            Some(code) => emit(fold_constants(code)),
+            None => self,
+        }
+    }
+}
+
+/// Decodes the chunk into instructions and their source lines.
+///
+/// Returns `None` when the chunk contains invalid opcodes or constant indices.
+fn decode(chunk: &Chunk) -> Option<Vec<(Instr, usize)>> {
+    let mut code = vec![];
+    let mut offset = 0;
+
+    while offset < chunk.len() {
+        let op = OpCode::from_u8(chunk.get_byte(offset))?;
+        let line = chunk.get_line(offset);
+        if offset + 1 + op.operand_len() > chunk.len() {
+            return None;
+        }
+
+        let instr = match op {
+            OpCode::Constant => {
+                let index = ConstantIndex::from_u8(chunk.get_byte(offset + 1));
+                Instr::Constant(*chunk.get_contant(index)?)
+            }
+            OpCode::ConstantLong => {
+                let index = ConstantIndex::from_parts(
+                    chunk.get_byte(offset + 1),
+                    chunk.get_byte(offset + 2),
+                    chunk.get_byte(offset + 3),
+                );
+                Instr::Constant(*chunk.get_contant(index)?)
+            }
+            _ => Instr::Op(op),
+        };
+
+        code.push((instr, line));
+        offset += 1 + op.operand_len();
+    }
+
+    Some(code)
+}
+
+/// Evaluates arithmetic on constants ahead of time, and drops `NoOp` instructions.
+///
+/// Operations that would result in a type error are kept, so the error is still
+/// raised at runtime. A folded constant takes the line of its operator.
+fn fold_constants(code: Vec<(Instr, usize)>) -> Vec<(Instr, usize)> {
+    let mut folded: Vec<(Instr, usize)> = Vec::with_capacity(code.len());
+
+    for (instr, line) in code {
+        let value = match (instr, folded.as_slice()) {
+            (Instr::Op(OpCode::NoOp), _) => continue,
+            (Instr::Op(OpCode::Negate), [.., (Instr::Constant(a), _)]) => Some((1, -*a)),
+            (Instr::Op(op), [.., (Instr::Constant(a), _), (Instr::Constant(b), _)]) => match op {
+                OpCode::Add => Some((2, *a + *b)),
+                OpCode::Subtract => Some((2, *a - *b)),
+                OpCode::Multiply => Some((2, *a * *b)),
+                OpCode::Divide => Some((2, *a / *b)),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        match value {
+            Some((operands, value)) if !value.is_err() => {
+                folded.truncate(folded.len() - operands);
+                folded.push((Instr::Constant(value), line));
+            }
+            _ => folded.push((instr, line)),
+        }
+    }
+
+    folded
+}
+
+/// Writes the instructions into a new chunk.
+fn emit(code: Vec<(Instr, usize)>) -> Chunk {
+    let mut chunk = Chunk::new();
+
+    for (instr, line) in code {
+        match instr {
+            Instr::Constant(value) => {
+                let index = chunk.add_constant(value);
+                let op = if index.is_u8() {
+                    OpCode::Constant
+                } else {
+                    OpCode::ConstantLong
+                };
+                chunk.write(op, line);
+                chunk.write(index, line);
+            }
+            Instr::Op(op) => chunk.write(op, line),
+        }
+    }
+
+    chunk
+}
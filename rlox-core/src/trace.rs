@@ -0,0 +1,392 @@
+//! Execution tracing.
+//!
+//! A [`Tracer`] installed on a [`LoxVm`](struct.LoxVm.html) is informed of every
+//! instruction it executes, and of how execution ends. When no tracer is installed
+//! the VM runs a dispatch loop without any tracing hooks.
+use crate::{chunk::Chunk, error::LoxError, opcode::OpCode, value::Value};
+use num_traits::FromPrimitive;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as FmtWrite,
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Hooks called by the VM during execution. Every hook does nothing by default.
+pub trait Tracer {
+    /// Called before the instruction at `offset` in the chunk is executed.
+    fn instruction(&mut self, _chunk: &Chunk, _offset: usize, _stack: &[Value]) {}
+
+    /// Called when execution returns a value.
+    fn returned(&mut self, _value: &Value) {}
+
+    /// Called when execution stops with an error.
+    ///
+    /// Stops that can be resumed are reported to [`paused`](#method.paused)
+    /// and [`interrupted`](#method.interrupted) instead.
+    fn error(&mut self, _error: &LoxError) {}
+
+    /// Called when execution stops at a breakpoint, or after a step.
+    fn paused(&mut self) {}
+
+    /// Called when execution runs out of fuel, or is stopped by an interrupt handle.
+    fn interrupted(&mut self) {}
+}
+
+/// Writes the value stack and the disassembled instruction for every step.
+pub struct WriteTracer<W: io::Write> {
+    writer: W,
+    buf: String,
+}
+
+impl<W: io::Write> WriteTracer<W> {
+    pub fn new(writer: W) -> Self {
+        WriteTracer {
+            writer,
+            buf: String::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: io::Write> Tracer for WriteTracer<W> {
+    fn instruction(&mut self, chunk: &Chunk, offset: usize, stack: &[Value]) {
+        self.buf.clear();
+        // Writing into a `String` can't fail.
+        let _ = chunk.disassemble_instruction(&mut self.buf, offset);
+        // Tracing must not interfere with execution, so write errors are ignored.
+        let _ = write!(self.writer, "{:?}\n{}", stack, self.buf);
+    }
+
+    fn returned(&mut self, value: &Value) {
+        let _ = writeln!(self.writer, "return {}", value);
+    }
+
+    fn error(&mut self, error: &LoxError) {
+        let _ = writeln!(self.writer, "error: {}", error);
+    }
+
+    fn paused(&mut self) {
+        let _ = writeln!(self.writer, "paused");
+    }
+
+    fn interrupted(&mut self) {
+        let _ = writeln!(self.writer, "interrupted");
+    }
+}
+
+/// Writes one JSON object per event, one event per line.
+///
+/// ```text
+/// {"event":"instruction","offset":0,"line":1,"op":"Constant","stack":[]}
+/// {"event":"return","value":"7"}
+/// {"event":"error","error":"type error"}
+/// {"event":"paused"}
+/// ```
+///
+/// Values are written as their display strings, since numbers like `NaN` have no JSON form.
+pub struct JsonTracer<W: io::Write> {
+    writer: W,
+    buf: String,
+}
+
+impl<W: io::Write> JsonTracer<W> {
+    pub fn new(writer: W) -> Self {
+        JsonTracer {
+            writer,
+            buf: String::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_line(&mut self) {
+        self.buf.push('\n');
+        let _ = self.writer.write_all(self.buf.as_bytes());
+        self.buf.clear();
+    }
+}
+
+impl<W: io::Write> Tracer for JsonTracer<W> {
+    fn instruction(&mut self, chunk: &Chunk, offset: usize, stack: &[Value]) {
+        let op = OpCode::from_u8(chunk.get_byte(offset));
+        let _ = write!(
+            self.buf,
+            r#"{{"event":"instruction","offset":{},"line":{},"op":"{:?}","stack":["#,
+            offset,
+            chunk.get_line(offset),
+            op.unwrap_or(OpCode::NoOp),
+        );
+        for (index, value) in stack.iter().enumerate() {
+            if index > 0 {
+                self.buf.push(',');
+            }
+            let _ = write!(self.buf, r#""{}""#, value);
+        }
+        self.buf.push_str("]}");
+        self.write_line();
+    }
+
+    fn returned(&mut self, value: &Value) {
+        let _ = write!(self.buf, r#"{{"event":"return","value":"{}"}}"#, value);
+        self.write_line();
+    }
+
+    fn error(&mut self, error: &LoxError) {
+        let _ = write!(self.buf, r#"{{"event":"error","error":"{}"}}"#, error);
+        self.write_line();
+    }
+
+    fn paused(&mut self) {
+        self.buf.push_str(r#"{"event":"paused"}"#);
+        self.write_line();
+    }
+
+    fn interrupted(&mut self) {
+        self.buf.push_str(r#"{"event":"interrupted"}"#);
+        self.write_line();
+    }
+}
+
+/// Event captured by a [`Recorder`].
+#[derive(Debug, Clone)]
+pub enum TraceEvent {
+    Instruction {
+        offset: usize,
+        op: OpCode,
+        stack: Vec<Value>,
+    },
+    Return(Value),
+    Error(LoxError),
+    Paused,
+    Interrupted,
+}
+
+/// Keeps every event in memory, for inspecting execution in tests.
+///
+/// The recorder is a handle to shared storage. Install a clone on the VM,
+/// and read the events through the original.
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    events: Arc<Mutex<Vec<TraceEvent>>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes and returns the events recorded so far.
+    pub fn take(&self) -> Vec<TraceEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+
+    fn push(&self, event: TraceEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl Tracer for Recorder {
+    fn instruction(&mut self, chunk: &Chunk, offset: usize, stack: &[Value]) {
+        if let Some(op) = OpCode::from_u8(chunk.get_byte(offset)) {
+            self.push(TraceEvent::Instruction {
+                offset,
+                op,
+                stack: stack.to_vec(),
+            });
+        }
+    }
+
+    fn returned(&mut self, value: &Value) {
+        self.push(TraceEvent::Return(*value));
+    }
+
+    fn error(&mut self, error: &LoxError) {
+        self.push(TraceEvent::Error(error.clone()));
+    }
+
+    fn paused(&mut self) {
+        self.push(TraceEvent::Paused);
+    }
+
+    fn interrupted(&mut self) {
+        self.push(TraceEvent::Interrupted);
+    }
+}
+
+/// Counts executed instructions and measures time spent per source line.
+///
+/// Like [`Recorder`], the profiler is a handle to shared storage. Install a clone
+/// on the VM, and read the [`Profile`] through the original.
+///
+/// The time of an instruction is measured until the next instruction starts, so it
+/// includes the cost of the hook itself. Relative times between lines are meaningful,
+/// absolute times are inflated.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    state: Arc<Mutex<ProfilerState>>,
+}
+
+#[derive(Debug, Default)]
+struct ProfilerState {
+    profile: Profile,
+    /// Line of the instruction currently executing, and when it started.
+    current: Option<(usize, Instant)>,
+}
+
+impl ProfilerState {
+    /// Charges the time since the current instruction started to its line.
+    fn finish(&mut self, now: Instant) {
+        if let Some((line, start)) = self.current.take() {
+            let elapsed = now - start;
+            self.profile.lines.entry(line).or_default().time += elapsed;
+            self.profile.time += elapsed;
+        }
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes and returns the profile collected so far.
+    pub fn take(&self) -> Profile {
+        std::mem::take(&mut self.state.lock().unwrap().profile)
+    }
+}
+
+impl Tracer for Profiler {
+    fn instruction(&mut self, chunk: &Chunk, offset: usize, _stack: &[Value]) {
+        let now = Instant::now();
+        let line = chunk.get_line(offset);
+
+        let mut state = self.state.lock().unwrap();
+        state.finish(now);
+        state.profile.opcodes[chunk.get_byte(offset) as usize] += 1;
+        state.profile.lines.entry(line).or_default().instructions += 1;
+        state.current = Some((line, now));
+    }
+
+    fn returned(&mut self, _value: &Value) {
+        self.state.lock().unwrap().finish(Instant::now());
+    }
+
+    fn error(&mut self, _error: &LoxError) {
+        self.state.lock().unwrap().finish(Instant::now());
+    }
+
+    // Time spent stopped isn't charged to the last line.
+    fn paused(&mut self) {
+        self.state.lock().unwrap().finish(Instant::now());
+    }
+
+    fn interrupted(&mut self) {
+        self.state.lock().unwrap().finish(Instant::now());
+    }
+}
+
+/// Instruction counts and timings collected by a [`Profiler`].
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// Executed instructions, indexed by opcode.
+    opcodes: Vec<u64>,
+    lines: BTreeMap<usize, LineProfile>,
+    time: Duration,
+}
+
+/// Cost of a single source line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineProfile {
+    /// Number of instructions executed on the line.
+    pub instructions: u64,
+    /// Time spent executing the line's instructions.
+    pub time: Duration,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            opcodes: vec![0; OpCode::COUNT as usize],
+            lines: BTreeMap::new(),
+            time: Duration::ZERO,
+        }
+    }
+}
+
+impl Profile {
+    /// Returns the number of times the given opcode was executed.
+    pub fn opcode_count(&self, op: OpCode) -> u64 {
+        self.opcodes[op as usize]
+    }
+
+    /// Returns the total number of instructions executed.
+    pub fn instructions(&self) -> u64 {
+        self.opcodes.iter().sum()
+    }
+
+    /// Returns the total time spent executing instructions.
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    /// Returns the cost of every executed line, in line order.
+    pub fn lines(&self) -> impl Iterator<Item = (usize, &LineProfile)> + '_ {
+        self.lines.iter().map(|(line, profile)| (*line, profile))
+    }
+
+    /// Returns the given number of lines that took the most time, most expensive first.
+    /// Ties are broken by instruction count.
+    pub fn hot_lines(&self, count: usize) -> Vec<(usize, LineProfile)> {
+        let mut lines = self
+            .lines
+            .iter()
+            .map(|(line, profile)| (*line, *profile))
+            .collect::<Vec<_>>();
+        lines.sort_by(|(_, a), (_, b)| b.time.cmp(&a.time).then(b.instructions.cmp(&a.instructions)));
+        lines.truncate(count);
+        lines
+    }
+
+    /// Writes the profile in the folded stack format read by flamegraph tools.
+    ///
+    /// ```text
+    /// script;line 1 2
+    /// script;line 2 1
+    /// ```
+    ///
+    /// Each line is a frame below the script, weighted by its instruction count,
+    /// so the output is the same on every run.
+    pub fn write_folded<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        for (line, profile) in &self.lines {
+            writeln!(writer, "script;line {} {}", line, profile.instructions)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the source with the instruction count and time of each line in front of it.
+    ///
+    /// Lines are numbered from 1. Lines that executed nothing are left blank.
+    pub fn write_annotated<W: io::Write>(&self, source: &str, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{:>10} {:>10} | source", "instrs", "micros")?;
+        for (index, text) in source.lines().enumerate() {
+            match self.lines.get(&(index + 1)) {
+                Some(profile) => writeln!(
+                    writer,
+                    "{:>10} {:>10} | {}",
+                    profile.instructions,
+                    profile.time.as_micros(),
+                    text
+                )?,
+                None => writeln!(writer, "{:>10} {:>10} | {}", "", "", text)?,
+            }
+        }
+        Ok(())
+    }
+}
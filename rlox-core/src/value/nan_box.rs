@@ -0,0 +1,152 @@
+//! Value packed into a NaN-boxed 64-bit word.
+//!
+//! Any bit pattern that is not a quiet NaN with the bits of [`QNAN`] set is a
+//! number. Other values are encoded in the low bits of such a NaN:
+//!
+//! ```text
+//! 0 11111111111 11 00 ... 0001  null
+//! 0 11111111111 11 00 ... 0010  error
+//! 0 11111111111 11 00 ... 0011  false (reserved)
+//! 0 11111111111 11 00 ... 0100  true (reserved)
+//! 1 11111111111 11 <pointer>    object (reserved)
+//! ```
+//!
+//! Numbers that are NaN are stored as the canonical NaN, so they can't be
+//! confused with a boxed value.
+use std::{
+    fmt,
+    ops::{Add, Div, Mul, Neg, Sub},
+};
+
+/// Bits that are set in every boxed value that is not a number.
+const QNAN: u64 = 0x7ffc_0000_0000_0000;
+
+/// Set together with [`QNAN`] on boxed object pointers.
+#[allow(dead_code)]
+const SIGN_BIT: u64 = 0x8000_0000_0000_0000;
+
+const TAG_NULL: u64 = 1;
+const TAG_ERR: u64 = 2;
+
+const NULL: u64 = QNAN | TAG_NULL;
+const ERR: u64 = QNAN | TAG_ERR;
+
+#[derive(Clone, Copy)]
+pub struct Value(u64);
+
+impl Value {
+    pub fn as_f64(&self) -> Option<f64> {
+        if self.is_float() {
+            Some(f64::from_bits(self.0))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        self.0 == NULL
+    }
+
+    /// Indicates that the value is the result of an operation on unsupported types.
+    #[inline]
+    pub fn is_err(&self) -> bool {
+        self.0 == ERR
+    }
+
+    #[inline(always)]
+    fn is_float(&self) -> bool {
+        self.0 & QNAN != QNAN
+    }
+
+    #[inline(always)]
+    fn float(value: f64) -> Self {
+        if value.is_nan() {
+            Value(f64::NAN.to_bits())
+        } else {
+            Value(value.to_bits())
+        }
+    }
+
+    /// Applies the operator to two numbers, or results in an error.
+    #[inline(always)]
+    fn binary(self, rhs: Self, op: impl FnOnce(f64, f64) -> f64) -> Self {
+        if self.is_float() && rhs.is_float() {
+            Value::float(op(f64::from_bits(self.0), f64::from_bits(rhs.0)))
+        } else {
+            Value(ERR)
+        }
+    }
+}
+
+impl Default for Value {
+    #[inline]
+    fn default() -> Self {
+        Value(NULL)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::float(value)
+    }
+}
+
+/// Formats like the enum representation, so debug output doesn't depend on the feature.
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.as_f64() {
+            Some(value) => f.debug_tuple("Float").field(&value).finish(),
+            None if self.is_null() => write!(f, "Null"),
+            None => write!(f, "Err"),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.as_f64() {
+            Some(value) => fmt::Display::fmt(&value, f),
+            None if self.is_null() => write!(f, "null"),
+            None => write!(f, "error"),
+        }
+    }
+}
+
+impl Neg for Value {
+    type Output = Self;
+    fn neg(self) -> Self {
+        match self.as_f64() {
+            Some(v) => Value::float(-v),
+            None => Value(ERR),
+        }
+    }
+}
+
+impl Add for Value {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        self.binary(rhs, |a, b| a + b)
+    }
+}
+
+impl Sub for Value {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        self.binary(rhs, |a, b| a - b)
+    }
+}
+
+impl Mul for Value {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        self.binary(rhs, |a, b| a * b)
+    }
+}
+
+impl Div for Value {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        self.binary(rhs, |a, b| a / b)
+    }
+}
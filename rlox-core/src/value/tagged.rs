@@ -0,0 +1,112 @@
+//! Value represented as a Rust enum.
+use std::{
+    fmt,
+    ops::{Add, Div, Mul, Neg, Sub},
+};
+
+#[derive(Clone, Copy)]
+pub struct Value(Repr);
+
+/// Kept private so the enum exposes the same surface as the NaN-boxed representation.
+#[derive(Debug, Clone, Copy)]
+enum Repr {
+    Null,
+    Float(f64),
+    Err,
+}
+
+impl Value {
+    pub fn as_f64(&self) -> Option<f64> {
+        match self.0 {
+            Repr::Float(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        matches!(self.0, Repr::Null)
+    }
+
+    /// Indicates that the value is the result of an operation on unsupported types.
+    #[inline]
+    pub fn is_err(&self) -> bool {
+        matches!(self.0, Repr::Err)
+    }
+
+    /// Applies the operator to two numbers, or results in an error.
+    #[inline(always)]
+    fn binary(self, rhs: Self, op: impl FnOnce(f64, f64) -> f64) -> Self {
+        match (self.0, rhs.0) {
+            (Repr::Float(a), Repr::Float(b)) => Value(Repr::Float(op(a, b))),
+            _ => Value(Repr::Err),
+        }
+    }
+}
+
+impl Default for Value {
+    #[inline]
+    fn default() -> Self {
+        Value(Repr::Null)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value(Repr::Float(value))
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Repr::Null => write!(f, "null"),
+            Repr::Float(value) => fmt::Display::fmt(&value, f),
+            Repr::Err => write!(f, "error"),
+        }
+    }
+}
+
+impl Neg for Value {
+    type Output = Self;
+    fn neg(self) -> Self {
+        match self.0 {
+            Repr::Float(v) => Value(Repr::Float(-v)),
+            _ => Value(Repr::Err),
+        }
+    }
+}
+
+impl Add for Value {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        self.binary(rhs, |a, b| a + b)
+    }
+}
+
+impl Sub for Value {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        self.binary(rhs, |a, b| a - b)
+    }
+}
+
+impl Mul for Value {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        self.binary(rhs, |a, b| a * b)
+    }
+}
+
+impl Div for Value {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        self.binary(rhs, |a, b| a / b)
+    }
+}
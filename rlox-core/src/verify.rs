@@ -0,0 +1,116 @@
+//! Bytecode verification.
+use crate::{
+    chunk::{Chunk, ConstantIndex},
+    error::{self, LoxError},
+    opcode::OpCode,
+};
+use std::ops::Deref;
+
+/// Chunk that has been checked once, so the VM can execute it without
+/// decoding into `Option<OpCode>` or bounds checking every fetch.
+///
+/// Every opcode is valid, every instruction has all of its operand bytes,
+/// every constant index refers to a constant in the chunk, no instruction pops
+/// more values than are on the stack, and the code ends with
+/// [`OpCode::Return`](enum.OpCode.html#variant.Return).
+///
+/// Verify a chunk once to run it many times with
+/// [`LoxVm::interpret_verified`](struct.LoxVm.html#method.interpret_verified).
+#[derive(Clone)]
+pub struct VerifiedChunk {
+    chunk: Chunk,
+}
+
+impl VerifiedChunk {
+    /// Verifies the chunk, appending a `Return` when the code doesn't end with one.
+    ///
+    /// Malformed chunks are rejected with `LoxError::Compile`.
+    pub fn new(mut chunk: Chunk) -> error::Result<Self> {
+        let code = chunk.code();
+        let constant_count = chunk.constant_count();
+        let mut offset = 0;
+        let mut last = None;
+        // Chunks are straight-line code, so the stack depth at each instruction is known.
+        let mut depth = 0usize;
+
+        while offset < code.len() {
+            let byte = code[offset];
+            if byte >= OpCode::COUNT {
+                return Err(LoxError::Compile);
+            }
+            // SAFETY: Checked against the number of opcodes above.
+            let op = unsafe { OpCode::from_u8_unchecked(byte) };
+
+            let index = match op {
+                OpCode::Constant => match code.get(offset + 1) {
+                    Some(&index) => Some(ConstantIndex::from_u8(index)),
+                    None => return Err(LoxError::Compile),
+                },
+                OpCode::ConstantLong => match code.get(offset + 1..offset + 4) {
+                    Some(&[x, y, z]) => Some(ConstantIndex::from_parts(x, y, z)),
+                    _ => return Err(LoxError::Compile),
+                },
+                _ => None,
+            };
+            if index.is_some_and(|index| index.to_usize() >= constant_count) {
+                return Err(LoxError::Compile);
+            }
+
+            let (pops, pushes) = op.stack_effect();
+            depth = match depth.checked_sub(pops) {
+                Some(depth) => depth + pushes,
+                None => return Err(LoxError::Compile),
+            };
+
+            last = Some(op);
+            offset += 1 + op.operand_len();
+        }
+
+        // The VM stops at a `Return`, so it never needs to check for the end of the code.
+        if last != Some(OpCode::Return) {
+            let line = chunk.last_line();
+            chunk.write_op(OpCode::Return, line);
+        }
+
+        Ok(VerifiedChunk { chunk })
+    }
+
+    pub fn into_inner(self) -> Chunk {
+        self.chunk
+    }
+
+    /// Retrieve a byte instruction from the chunk code, without bounds checking.
+    ///
+    /// # Safety
+    ///
+    /// The offset must point into the code. Offsets reached by executing
+    /// instructions in order are in bounds, since the code ends with `Return`.
+    #[inline(always)]
+    pub(crate) unsafe fn get_byte_unchecked(&self, offset: usize) -> u8 {
+        self.chunk.get_byte_unchecked(offset)
+    }
+
+    /// Decodes the opcode at the given offset.
+    ///
+    /// # Safety
+    ///
+    /// The offset must point to the start of an instruction.
+    #[inline(always)]
+    pub(crate) unsafe fn get_opcode_unchecked(&self, offset: usize) -> OpCode {
+        OpCode::from_u8_unchecked(self.get_byte_unchecked(offset))
+    }
+}
+
+impl Default for VerifiedChunk {
+    fn default() -> Self {
+        VerifiedChunk::new(Chunk::new()).expect("empty chunk is valid")
+    }
+}
+
+impl Deref for VerifiedChunk {
+    type Target = Chunk;
+
+    fn deref(&self) -> &Chunk {
+        &self.chunk
+    }
+}
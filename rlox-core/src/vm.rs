@@ -2,81 +2,195 @@
 use crate::chunk::ConstantIndex;
 use crate::{
     chunk::Chunk,
+    config::VmConfig,
     error::{self, LoxError},
+    interrupt::InterruptHandle,
     opcode::OpCode,
+    trace::Tracer,
     value::Value,
+    verify::VerifiedChunk,
 };
-use num_traits::FromPrimitive;
-use rlox_derive::array_init;
-#[cfg(feature = "trace-execution")]
-use std::fmt::Write as FmtWrite;
+use std::collections::BTreeSet;
 
 /// Helper for handling type checking on expressions that result in a `Value` containing a numerical type.
 #[doc(hidden)]
 macro_rules! arithmetic_op {
     ($vm:ident, $op:expr) => {
         match $op {
-            value @ Value::Float(_) => $vm.push(value),
-            Value::Err => return Err(LoxError::TypeError),
-            _ => unreachable!("Operator not implemented"),
+            value if value.is_err() => return Err(LoxError::TypeError),
+            value => $vm.push(value)?,
         }
     };
 }
 
 pub struct LoxVm {
-    chunk: Chunk,
+    config: VmConfig,
+    chunk: VerifiedChunk,
     ip: usize,
-    /// Index to element just past the top element in the value stack.
-    top: usize,
-    stack: [Value; LoxVm::STACK_MAX],
+    /// Value stack. Grows on demand up to `VmConfig::max_stack`.
+    stack: Vec<Value>,
+    /// Number of instructions left to execute before the VM is interrupted.
+    /// `None` means execution is not metered.
+    fuel: Option<u64>,
+    interrupt: InterruptHandle,
+    tracer: Option<Box<dyn Tracer + Send>>,
+    /// Source lines where execution pauses.
+    breakpoints: BTreeSet<usize>,
+    /// Pause when execution reaches a new line, set for the duration of a [`step`](#method.step).
+    stepping: bool,
+    /// Line of the last instruction checked for breakpoints, so execution
+    /// only pauses when it enters a line.
+    line: Option<usize>,
+    /// How the last run stopped, which decides whether it can be resumed.
+    state: RunState,
 }
 
-impl LoxVm {
-    const STACK_MAX: usize = 256;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunState {
+    /// Execution returned a value, or no chunk has run yet.
+    Finished,
+    /// Execution stopped with an error that can't be resumed.
+    Failed,
+    Interrupted,
+    Paused,
+}
 
+impl LoxVm {
     pub fn new() -> Self {
+        Self::with_config(VmConfig::default())
+    }
+
+    pub fn with_config(config: VmConfig) -> Self {
         Self {
-            chunk: Chunk::new(),
+            chunk: VerifiedChunk::default(),
             ip: 0,
-            top: 0,
-            stack: array_init!(Value, [Value::Null; LoxVm::STACK_MAX]),
+            stack: Vec::with_capacity(config.initial_stack),
+            fuel: None,
+            interrupt: InterruptHandle::default(),
+            tracer: None,
+            breakpoints: BTreeSet::new(),
+            stepping: false,
+            line: None,
+            state: RunState::Finished,
+            config,
+        }
+    }
+
+    #[inline]
+    pub fn config(&self) -> &VmConfig {
+        &self.config
+    }
+
+    /// Limits execution to the given number of instructions.
+    ///
+    /// When the fuel runs out, execution stops with `LoxError::Interrupted`. Add more
+    /// fuel and call [`resume`](#method.resume) to continue.
+    pub fn set_fuel(&mut self, fuel: u64) {
+        self.fuel = Some(fuel);
+    }
+
+    /// Removes the instruction limit.
+    pub fn clear_fuel(&mut self) {
+        self.fuel = None;
+    }
+
+    /// Returns the number of instructions left to execute, if execution is metered.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Installs a tracer that is informed of every instruction executed.
+    pub fn set_tracer(&mut self, tracer: impl Tracer + Send + 'static) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    /// Removes the installed tracer, if any.
+    pub fn clear_tracer(&mut self) -> Option<Box<dyn Tracer + Send>> {
+        self.tracer.take()
+    }
+
+    /// Pauses execution before the first instruction of the given source line.
+    ///
+    /// Execution stops with `LoxError::Paused`, and can be continued with
+    /// [`resume`](#method.resume) or [`step`](#method.step).
+    pub fn set_breakpoint(&mut self, line: usize) {
+        self.breakpoints.insert(line);
+    }
+
+    /// Removes the breakpoint on the given line, returning whether one was set.
+    pub fn clear_breakpoint(&mut self, line: usize) -> bool {
+        self.breakpoints.remove(&line)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Continues execution until it reaches the next source line, where it stops
+    /// with `LoxError::Paused`. Returns the result of the chunk if it finishes first.
+    pub fn step(&mut self) -> error::Result<Value> {
+        self.stepping = true;
+        let result = self.resume();
+        self.stepping = false;
+        result
+    }
+
+    /// Returns the source line of the next instruction to execute, or `None`
+    /// when execution has finished.
+    pub fn line(&self) -> Option<usize> {
+        if self.ip < self.chunk.len() {
+            Some(self.chunk.get_line(self.ip))
+        } else {
+            None
         }
     }
 
+    /// Returns the value stack, from bottom to top.
+    ///
+    /// The stack is left as is when execution pauses or stops with an error, so it can be inspected.
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
+    }
+
+    /// Returns a handle that can interrupt the VM from another thread.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
     #[allow(dead_code)]
     #[inline]
     fn peek_mut(&mut self, offset: isize) -> &mut Value {
-        // Top cursor points to the element just after the actual top element.
-        let index = -1 + self.top as isize + offset;
+        // Offset 0 is the top element.
+        let index = self.stack.len() as isize - 1 + offset;
         assert!(index >= 0, "Stack underflow");
-        assert!(index < Self::STACK_MAX as isize, "Stack overflow");
 
-        unsafe { self.stack.get_unchecked_mut(index as usize) }
+        &mut self.stack[index as usize]
     }
 
     #[inline]
-    fn push(&mut self, value: Value) {
+    fn push(&mut self, value: Value) -> error::Result<()> {
         #[cfg(feature = "profile")]
         let _ = flame::start_guard("vm push");
 
-        assert!(self.top < Self::STACK_MAX, "Stack overflow");
+        if self.stack.len() >= self.config.max_stack {
+            return Err(LoxError::StackOverflow);
+        }
 
-        // Top index points to just past the top element.
-        self.stack[self.top] = value;
-        self.top += 1;
+        self.stack.push(value);
+        Ok(())
     }
 
+    /// Pops the top value. An empty stack is a `LoxError::Runtime`, which a verified chunk never causes.
     #[inline]
-    fn pop(&mut self) -> Value {
+    fn pop(&mut self) -> error::Result<Value> {
         #[cfg(feature = "profile")]
         let _ = flame::start_guard("vm pop");
 
-        assert!(self.top > 0, "Stack underflow");
-
-        self.top -= 1;
-        let mut value = Value::Null;
-        std::mem::swap(&mut value, &mut self.stack[self.top]);
-        value
+        self.stack.pop().ok_or(LoxError::Runtime)
     }
 
     #[inline]
@@ -84,139 +198,228 @@ impl LoxVm {
         #[cfg(feature = "profile")]
         let _ = flame::start_guard("vm try_pop");
 
-        if self.top >= 1 {
-            self.top -= 1;
-        }
-        let mut value = Value::Null;
-        std::mem::swap(&mut value, &mut self.stack[self.top]);
-        value
+        self.stack.pop().unwrap_or_default()
     }
 
+    /// Executes the chunk.
+    ///
+    /// The chunk is verified before it runs. Malformed chunks, and chunks with more
+    /// constants than `VmConfig::max_constants`, are rejected with `LoxError::Compile`.
     pub fn interpret(&mut self, chunk: Chunk) -> error::Result<Value> {
+        self.interpret_verified(VerifiedChunk::new(chunk)?)
+    }
+
+    /// Executes a chunk that has already been verified.
+    ///
+    /// Chunks with more constants than `VmConfig::max_constants` are rejected with `LoxError::Compile`.
+    pub fn interpret_verified(&mut self, chunk: VerifiedChunk) -> error::Result<Value> {
+        if chunk.constant_count() > self.config.max_constants {
+            return Err(LoxError::Compile);
+        }
+
         self.chunk = chunk;
         self.ip = 0;
+        self.line = None;
+        // Values left behind by an earlier chunk that stopped with an error.
+        self.stack.clear();
 
-        if self.chunk.len() > 0 {
-            self.run()
-        } else {
-            Ok(Value::Null)
+        self.run()
+    }
+
+    /// Continues execution of the current chunk after an interrupt or a pause,
+    /// from the saved instruction pointer and value stack.
+    ///
+    /// Returns `LoxError::NotResumable` when the last run finished, or stopped with any other error.
+    pub fn resume(&mut self) -> error::Result<Value> {
+        match self.state {
+            RunState::Interrupted | RunState::Paused => self.run(),
+            RunState::Finished | RunState::Failed => Err(LoxError::NotResumable),
         }
     }
 
+    /// Consumes one instruction's worth of fuel, and checks for a pending interrupt.
+    ///
+    /// Returns `true` when execution must stop before the next instruction.
+    #[inline(always)]
+    fn should_interrupt(&mut self) -> bool {
+        if self.interrupt.take() {
+            return true;
+        }
+
+        match self.fuel.as_mut() {
+            Some(0) => true,
+            Some(fuel) => {
+                *fuel -= 1;
+                false
+            }
+            None => false,
+        }
+    }
+
+    // SAFETY: Operand fetches stay in bounds, because a verified chunk only
+    //         contains complete instructions.
     #[inline(always)]
     fn get_byte(&mut self) -> u8 {
-        let b = self.chunk.get_byte(self.ip);
+        let b = unsafe { self.chunk.get_byte_unchecked(self.ip) };
         self.ip += 1;
         b
     }
 
+    #[inline(always)]
     fn get_3bytes(&mut self) -> [u8; 3] {
-        let x = self.chunk.get_byte(self.ip);
-        let y = self.chunk.get_byte(self.ip + 1);
-        let z = self.chunk.get_byte(self.ip + 2);
+        let bytes = unsafe {
+            [
+                self.chunk.get_byte_unchecked(self.ip),
+                self.chunk.get_byte_unchecked(self.ip + 1),
+                self.chunk.get_byte_unchecked(self.ip + 2),
+            ]
+        };
         self.ip += 3;
-        [x, y, z]
-    }
-
-    /// Checks whether the instruction pointer is at the end of the chunk.
-    fn at_end(&self) -> bool {
-        self.ip >= self.chunk.len()
+        bytes
     }
 
     fn run(&mut self) -> error::Result<Value> {
         #[cfg(feature = "profile")]
         let _ = flame::start_guard("vm run");
 
-        #[cfg(feature = "trace-execution")]
-        let mut buf = String::new();
+        let result = if self.tracer.is_none() && self.breakpoints.is_empty() && !self.stepping {
+            self.dispatch::<false>()
+        } else {
+            self.dispatch::<true>()
+        };
+
+        self.state = match result {
+            Ok(_) => RunState::Finished,
+            Err(LoxError::Interrupted) => RunState::Interrupted,
+            Err(LoxError::Paused) => RunState::Paused,
+            Err(_) => RunState::Failed,
+        };
+
+        if let Some(tracer) = self.tracer.as_mut() {
+            match &result {
+                Ok(value) => tracer.returned(value),
+                Err(LoxError::Paused) => tracer.paused(),
+                Err(LoxError::Interrupted) => tracer.interrupted(),
+                Err(err) => tracer.error(err),
+            }
+        }
+        result
+    }
+
+    /// Checks whether execution enters a line where it must pause.
+    #[inline]
+    fn should_pause(&mut self) -> bool {
+        let line = self.chunk.get_line(self.ip);
+        if self.line == Some(line) {
+            return false;
+        }
 
+        // Remembered before pausing, so resuming doesn't pause on the same line again.
+        self.line = Some(line);
+        self.stepping || self.breakpoints.contains(&line)
+    }
+
+    /// Runs the dispatch loop. Tracing and debugging hooks are only compiled into the loop when `HOOKS` is set.
+    fn dispatch<const HOOKS: bool>(&mut self) -> error::Result<Value> {
         loop {
             #[cfg(feature = "profile")]
             let _ = flame::start_guard("vm loop");
 
-            #[cfg(feature = "trace-execution")]
-            {
-                println!("{:?}", &self.stack[0..self.top]);
-                self.chunk.disassemble_instruction(&mut buf, self.ip).unwrap();
-                print!("{}", buf);
-                buf.clear();
+            // Checked before fuel is consumed, so pausing doesn't use up an instruction.
+            if HOOKS && self.should_pause() {
+                return Err(LoxError::Paused);
+            }
+
+            if self.should_interrupt() {
+                return Err(LoxError::Interrupted);
             }
 
-            if self.at_end() {
-                return Ok(self.try_pop());
+            if HOOKS {
+                if let Some(tracer) = self.tracer.as_mut() {
+                    tracer.instruction(&self.chunk, self.ip, &self.stack);
+                }
             }
 
-            let op = OpCode::from_u8(self.get_byte());
+            // SAFETY: The instruction pointer is at the start of an instruction, and
+            //         can't run past the end of a verified chunk, which ends with `Return`.
+            let op = unsafe { self.chunk.get_opcode_unchecked(self.ip) };
+            self.ip += 1;
 
             #[cfg(feature = "profile")]
             let _ = flame::start_guard("vm opcode dispatch");
 
             match op {
-                Some(OpCode::Constant) => {
+                OpCode::Constant => {
                     #[cfg(feature = "profile")]
                     let _ = flame::start_guard("opcode Constant");
 
                     let index = ConstantIndex::from_u8(self.get_byte());
-                    let constant = self.chunk.get_contant(index).cloned().unwrap_or(Value::Null);
-                    self.push(constant);
+                    let constant = *self.chunk.get_constant_unchecked(index);
+                    self.push(constant)?;
                 }
-                Some(OpCode::ConstantLong) => {
+                OpCode::ConstantLong => {
                     #[cfg(feature = "profile")]
                     let _ = flame::start_guard("opcode ConstantLong");
 
                     let [x, y, z] = self.get_3bytes();
                     let index = ConstantIndex::from_parts(x, y, z);
-                    let constant = self.chunk.get_contant(index).cloned().unwrap_or(Value::Null);
-                    self.push(constant);
+                    let constant = *self.chunk.get_constant_unchecked(index);
+                    self.push(constant)?;
                 }
-                Some(OpCode::Negate) => {
+                OpCode::Negate => {
                     #[cfg(feature = "profile")]
                     let _ = flame::start_guard("opcode Negate");
 
-                    let value = self.pop();
+                    let value = self.pop()?;
                     arithmetic_op!(self, -value);
                 }
-                Some(OpCode::Add) => {
+                OpCode::Add => {
                     #[cfg(feature = "profile")]
                     let _ = flame::start_guard("opcode Add");
 
-                    let b = self.pop();
-                    let a = self.pop();
+                    let b = self.pop()?;
+                    let a = self.pop()?;
                     arithmetic_op!(self, a + b);
                 }
-                Some(OpCode::Subtract) => {
+                OpCode::Subtract => {
                     #[cfg(feature = "profile")]
                     let _ = flame::start_guard("opcode Subtract");
 
-                    let b = self.pop();
-                    let a = self.pop();
+                    let b = self.pop()?;
+                    let a = self.pop()?;
                     arithmetic_op!(self, a - b);
                 }
-                Some(OpCode::Multiply) => {
+                OpCode::Multiply => {
                     #[cfg(feature = "profile")]
                     let _ = flame::start_guard("opcode Multiply");
 
-                    let b = self.pop();
-                    let a = self.pop();
+                    let b = self.pop()?;
+                    let a = self.pop()?;
                     arithmetic_op!(self, a * b);
                 }
-                Some(OpCode::Divide) => {
+                OpCode::Divide => {
                     #[cfg(feature = "profile")]
                     let _ = flame::start_guard("opcode Divide");
 
-                    let b = self.pop();
-                    let a = self.pop();
+                    let b = self.pop()?;
+                    let a = self.pop()?;
                     arithmetic_op!(self, a / b);
                 }
-                Some(OpCode::Return) => {
+                OpCode::Return => {
                     #[cfg(feature = "profile")]
                     let _ = flame::start_guard("opcode Return");
 
                     // println!("Interpret return {}", self.pop());
                     return Ok(self.try_pop());
                 }
-                _ => {}
+                OpCode::NoOp => {}
             }
         }
     }
 }
+
+impl Default for LoxVm {
+    fn default() -> Self {
+        LoxVm::new()
+    }
+}
@@ -0,0 +1,79 @@
+use rlox_core::{Chunk, LoxError, LoxVm, OpCode, OptLevel, Value};
+
+fn constant(chunk: &mut Chunk, value: impl Into<Value>, line: usize) {
+    let index = chunk.add_constant(value);
+    if index.is_u8() {
+        chunk.write(OpCode::Constant, line);
+    } else {
+        chunk.write(OpCode::ConstantLong, line);
+    }
+    chunk.write(index, line);
+}
+
+/// Builds a chunk for `-(1.2 + 3.4) * 2`, with the operations on separate lines.
+fn arithmetic_chunk() -> Chunk {
+    let mut chunk = Chunk::new();
+    constant(&mut chunk, 1.2, 1);
+    constant(&mut chunk, 3.4, 1);
+    chunk.write(OpCode::Add, 2);
+    chunk.write(OpCode::Negate, 3);
+    chunk.write(OpCode::NoOp, 3);
+    constant(&mut chunk, 2.0, 4);
+    chunk.write(OpCode::Multiply, 5);
+    chunk.write(OpCode::Return, 6);
+    chunk
+}
+
+#[test]
+fn test_fold_constants() {
+    let chunk = arithmetic_chunk().optimize(OptLevel::O1);
+    assert_eq!(chunk.constant_count(), 1);
+
+    // The folded constant keeps the line of the last operator.
+    assert_eq!(
+        chunk.disassemble_to_string().unwrap(),
+        "=== constants ===\n   0 Float(-9.2)\n=== code ===\n0000    5 Constant\t\t   0 '-9.2'\n0002    6 Return\n"
+    );
+
+    let value = LoxVm::new().interpret(chunk).expect("interpret failed");
+    assert_eq!(value.as_f64(), Some(-9.2));
+}
+
+#[test]
+fn test_opt_level_none() {
+    let chunk = arithmetic_chunk();
+    let disassembly = chunk.disassemble_to_string().unwrap();
+
+    let chunk = chunk.optimize(OptLevel::O0);
+    assert_eq!(chunk.disassemble_to_string().unwrap(), disassembly);
+}
+
+#[test]
+fn test_fold_keeps_type_errors() {
+    // `null + 1` must still fail at runtime.
+    let mut chunk = Chunk::new();
+    constant(&mut chunk, Value::default(), 1);
+    constant(&mut chunk, 1.0, 1);
+    chunk.write(OpCode::Add, 1);
+
+    let chunk = chunk.optimize(OptLevel::O1);
+    assert_eq!(chunk.constant_count(), 2);
+    assert!(matches!(LoxVm::new().interpret(chunk), Err(LoxError::TypeError)));
+}
+
+#[test]
+fn test_fold_long_constants() {
+    // Folding works through 24-bit constant indices, down to a single constant.
+    let mut chunk = Chunk::new();
+    for i in 0..300 {
+        constant(&mut chunk, i as f64, 1);
+    }
+    for _ in 0..299 {
+        chunk.write(OpCode::Add, 1);
+    }
+
+    let value = LoxVm::new().interpret(chunk.clone()).unwrap().as_f64();
+    let chunk = chunk.optimize(OptLevel::O1);
+    assert_eq!(chunk.constant_count(), 1);
+    assert_eq!(LoxVm::new().interpret(chunk).unwrap().as_f64(), value);
+}
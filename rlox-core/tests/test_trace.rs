@@ -0,0 +1,174 @@
+use rlox_core::{
+    trace::{JsonTracer, Profiler, Recorder, TraceEvent, WriteTracer},
+    Chunk, LoxError, LoxVm, OpCode, Value,
+};
+
+/// Builds a chunk that evaluates `-(1 + 2)`.
+fn negate_chunk() -> Chunk {
+    let mut chunk = Chunk::new();
+    for (value, line) in [(1.0, 1), (2.0, 1)] {
+        let index = chunk.add_constant(value);
+        chunk.write(OpCode::Constant, line);
+        chunk.write(index, line);
+    }
+    chunk.write(OpCode::Add, 2);
+    chunk.write(OpCode::Negate, 3);
+    chunk.write(OpCode::Return, 3);
+    chunk
+}
+
+#[test]
+fn test_recorder() {
+    let recorder = Recorder::new();
+    let mut vm = LoxVm::new();
+    vm.set_tracer(recorder.clone());
+
+    vm.interpret(negate_chunk()).unwrap();
+
+    let events = recorder.take();
+    let steps = events
+        .iter()
+        .filter_map(|event| match event {
+            TraceEvent::Instruction { offset, op, stack } => Some((*offset, *op, stack.len())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        steps,
+        vec![
+            (0, OpCode::Constant, 0),
+            (2, OpCode::Constant, 1),
+            (4, OpCode::Add, 2),
+            (5, OpCode::Negate, 1),
+            (6, OpCode::Return, 1),
+        ]
+    );
+    assert!(matches!(events.last(), Some(TraceEvent::Return(value)) if value.as_f64() == Some(-3.0)));
+}
+
+#[test]
+fn test_recorder_error() {
+    let mut chunk = Chunk::new();
+    let index = chunk.add_constant(Value::default());
+    chunk.write(OpCode::Constant, 1);
+    chunk.write(index, 1);
+    chunk.write(OpCode::Negate, 1);
+
+    let recorder = Recorder::new();
+    let mut vm = LoxVm::new();
+    vm.set_tracer(recorder.clone());
+
+    assert_eq!(vm.interpret(chunk).unwrap_err(), LoxError::TypeError);
+    assert!(matches!(
+        recorder.take().last(),
+        Some(TraceEvent::Error(LoxError::TypeError))
+    ));
+
+    // Nothing is recorded once the tracer is removed.
+    vm.clear_tracer();
+    vm.interpret(negate_chunk()).unwrap();
+    assert!(recorder.take().is_empty());
+}
+
+#[test]
+fn test_recorder_stops() {
+    let recorder = Recorder::new();
+    let mut vm = LoxVm::new();
+    vm.set_tracer(recorder.clone());
+
+    // Resumable stops are not recorded as errors.
+    vm.set_breakpoint(2);
+    assert_eq!(vm.interpret(negate_chunk()).unwrap_err(), LoxError::Paused);
+    assert!(matches!(recorder.take().last(), Some(TraceEvent::Paused)));
+
+    vm.clear_breakpoints();
+    vm.set_fuel(0);
+    assert_eq!(vm.resume().unwrap_err(), LoxError::Interrupted);
+    assert!(matches!(recorder.take()[..], [TraceEvent::Interrupted]));
+
+    vm.clear_fuel();
+    vm.resume().unwrap();
+    assert!(matches!(recorder.take().last(), Some(TraceEvent::Return(_))));
+}
+
+#[test]
+fn test_json_tracer() {
+    let mut vm = LoxVm::new();
+    vm.set_tracer(JsonTracer::new(std::io::sink()));
+    vm.interpret(negate_chunk()).unwrap();
+
+    let mut tracer = JsonTracer::new(vec![]);
+    rlox_core::trace::Tracer::instruction(&mut tracer, &negate_chunk(), 4, &[1.0.into(), 2.0.into()]);
+    rlox_core::trace::Tracer::returned(&mut tracer, &Value::from(-3.0));
+    rlox_core::trace::Tracer::error(&mut tracer, &LoxError::TypeError);
+    rlox_core::trace::Tracer::paused(&mut tracer);
+
+    assert_eq!(
+        String::from_utf8(tracer.into_inner()).unwrap(),
+        concat!(
+            r#"{"event":"instruction","offset":4,"line":2,"op":"Add","stack":["1","2"]}"#,
+            "\n",
+            r#"{"event":"return","value":"-3"}"#,
+            "\n",
+            r#"{"event":"error","error":"type error"}"#,
+            "\n",
+            r#"{"event":"paused"}"#,
+            "\n",
+        )
+    );
+}
+
+#[test]
+fn test_write_tracer() {
+    let mut tracer = WriteTracer::new(vec![]);
+    rlox_core::trace::Tracer::instruction(&mut tracer, &negate_chunk(), 5, &[3.0.into()]);
+    rlox_core::trace::Tracer::returned(&mut tracer, &Value::from(-3.0));
+
+    assert_eq!(
+        String::from_utf8(tracer.into_inner()).unwrap(),
+        "[Float(3.0)]\n0005    3 Negate\nreturn -3\n"
+    );
+}
+
+#[test]
+fn test_profiler() {
+    let profiler = Profiler::new();
+    let mut vm = LoxVm::new();
+    vm.set_tracer(profiler.clone());
+
+    vm.interpret(negate_chunk()).unwrap();
+    vm.interpret(negate_chunk()).unwrap();
+
+    let profile = profiler.take();
+    assert_eq!(profile.instructions(), 10);
+    assert_eq!(profile.opcode_count(OpCode::Constant), 4);
+    assert_eq!(profile.opcode_count(OpCode::Add), 2);
+    assert_eq!(profile.opcode_count(OpCode::Divide), 0);
+
+    let counts = profile
+        .lines()
+        .map(|(line, profile)| (line, profile.instructions))
+        .collect::<Vec<_>>();
+    assert_eq!(counts, vec![(1, 4), (2, 2), (3, 4)]);
+    assert_eq!(profile.hot_lines(1).len(), 1);
+
+    let mut folded = vec![];
+    profile.write_folded(&mut folded).unwrap();
+    assert_eq!(
+        String::from_utf8(folded).unwrap(),
+        "script;line 1 4\nscript;line 2 2\nscript;line 3 4\n"
+    );
+
+    let mut annotated = vec![];
+    profile
+        .write_annotated("1 + 2\n// sum\n-sum\nprint", &mut annotated)
+        .unwrap();
+    let annotated = String::from_utf8(annotated).unwrap();
+    let rows = annotated.lines().collect::<Vec<_>>();
+    assert_eq!(rows.len(), 5);
+    assert!(rows[1].trim_start().starts_with("4 ") && rows[1].ends_with("| 1 + 2"));
+    assert_eq!(rows[4], format!("{:>21} | print", ""));
+
+    // The profile is reset once taken.
+    assert_eq!(profiler.take().instructions(), 0);
+}
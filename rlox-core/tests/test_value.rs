@@ -0,0 +1,76 @@
+use rlox_core::Value;
+
+#[test]
+fn test_value_arithmetic() {
+    let (a, b) = (6.0, 1.5);
+
+    assert_eq!((Value::from(a) + Value::from(b)).as_f64(), Some(7.5));
+    assert_eq!((Value::from(a) - Value::from(b)).as_f64(), Some(4.5));
+    assert_eq!((Value::from(a) * Value::from(b)).as_f64(), Some(9.0));
+    assert_eq!((Value::from(a) / Value::from(b)).as_f64(), Some(4.0));
+    assert_eq!((-Value::from(a)).as_f64(), Some(-6.0));
+}
+
+#[test]
+fn test_value_null() {
+    let null = Value::default();
+    assert!(null.is_null());
+    assert_eq!(null.as_f64(), None);
+
+    let err = Value::default() + Value::from(1.0);
+    assert!(err.is_err());
+    assert!(!err.is_null());
+    assert!((-null).is_err());
+}
+
+#[test]
+fn test_value_nan() {
+    // A NaN result is still a number.
+    let nan = Value::from(0.0) / Value::from(0.0);
+    assert!(nan.as_f64().unwrap().is_nan());
+    assert!(!nan.is_null());
+    assert!(!nan.is_err());
+
+    let nan = Value::from(f64::from_bits(0x7fff_ffff_ffff_ffff));
+    assert!(nan.as_f64().unwrap().is_nan());
+}
+
+#[test]
+fn test_value_format() {
+    assert_eq!(Value::from(2.5).to_string(), "2.5");
+    assert_eq!(Value::default().to_string(), "null");
+    assert_eq!((Value::default() + Value::default()).to_string(), "error");
+
+    assert_eq!(format!("{:?}", Value::from(2.5)), "Float(2.5)");
+    assert_eq!(format!("{:?}", Value::default()), "Null");
+}
+
+#[cfg(feature = "nan-boxing")]
+#[test]
+fn test_value_size() {
+    assert_eq!(std::mem::size_of::<Value>(), 8);
+}
+
+#[test]
+fn test_value_conversion() {
+    use rlox_core::{ConversionError, FromLox, IntoLox};
+
+    assert_eq!(f64::from_lox(2.5.into_lox()), Ok(2.5));
+    assert_eq!(<()>::from_lox(().into_lox()), Ok(()));
+    assert_eq!(Option::<f64>::from_lox(None::<f64>.into_lox()), Ok(None));
+    assert_eq!(Option::<f64>::from_lox(Some(1.0).into_lox()), Ok(Some(1.0)));
+
+    let err = f64::from_lox(Value::default()).unwrap_err();
+    assert_eq!(
+        err,
+        ConversionError {
+            expected: "number",
+            found: "null"
+        }
+    );
+    assert_eq!(err.to_string(), "expected number, found null");
+    assert_eq!(
+        <()>::from_lox(1.0.into_lox()).unwrap_err().to_string(),
+        "expected null, found number"
+    );
+}
@@ -1,31 +1,276 @@
-use rlox_core::{Chunk, LoxVm, OpCode, Value};
+use rlox_core::{Chunk, LoxError, LoxVm, OpCode, Value, VmConfig};
+use rlox_gc::{Collector, CollectorConfig};
+use std::thread;
 
-#[test]
-fn test_arithmetic() {
+/// Builds a chunk that evaluates `1 + 2 * 3` in 5 instructions.
+fn arithmetic_chunk() -> Chunk {
     let mut chunk = Chunk::new();
 
     // 1 + 2 * 3
-    let const_0 = chunk.add_constant(Value::Float(3.0));
+    let const_0 = chunk.add_constant(3.0);
     chunk.write(OpCode::Constant, 0);
     chunk.write(const_0, 0);
 
-    let const_1 = chunk.add_constant(Value::Float(2.0));
+    let const_1 = chunk.add_constant(2.0);
     chunk.write(OpCode::Constant, 0);
     chunk.write(const_1, 0);
 
     chunk.write(OpCode::Multiply, 0);
 
     // test 24-bit constant index
-    let const_2 = chunk.add_constant_long(Value::Float(1.0));
+    let const_2 = chunk.add_constant_long(1.0);
     assert!(const_2.is_u24());
     chunk.write(OpCode::ConstantLong, 0);
     chunk.write(const_2, 0);
 
     chunk.write(OpCode::Add, 0);
+    chunk
+}
 
+#[test]
+fn test_arithmetic() {
+    let chunk = arithmetic_chunk();
     let mut vm = LoxVm::new();
 
     let value = vm.interpret(chunk).expect("interpret failed");
     println!("{value:?}");
     assert_eq!(value.as_f64(), Some(7.0));
 }
+
+#[test]
+fn test_out_of_memory() {
+    let mut heap = Collector::with_config(CollectorConfig {
+        max_heap_bytes: Some(0),
+        ..CollectorConfig::default()
+    });
+
+    let mut alloc = || -> rlox_core::Result<()> {
+        heap.try_alloc(1.0f64)?;
+        Ok(())
+    };
+    assert!(matches!(alloc(), Err(LoxError::OutOfMemory)));
+}
+
+#[test]
+fn test_invalid_chunk() {
+    let mut vm = LoxVm::new();
+
+    let mut chunk = Chunk::new();
+    chunk.write_u8(0xff, 0);
+    assert!(matches!(vm.interpret(chunk), Err(LoxError::Compile)));
+
+    // Missing operand.
+    let mut chunk = Chunk::new();
+    chunk.add_constant(1.0);
+    chunk.write(OpCode::Constant, 0);
+    assert!(matches!(vm.interpret(chunk), Err(LoxError::Compile)));
+
+    // Constant out of range.
+    let mut chunk = Chunk::new();
+    chunk.add_constant(1.0);
+    chunk.write(OpCode::Constant, 0);
+    chunk.write_u8(1, 0);
+    assert!(matches!(vm.interpret(chunk), Err(LoxError::Compile)));
+
+    // Stack underflow.
+    let mut chunk = Chunk::new();
+    chunk.write(OpCode::Add, 0);
+    assert!(matches!(vm.interpret(chunk), Err(LoxError::Compile)));
+
+    let mut chunk = Chunk::new();
+    let index = chunk.add_constant(1.0);
+    chunk.write(OpCode::Constant, 0);
+    chunk.write(index, 0);
+    chunk.write(OpCode::Negate, 0);
+    chunk.write(OpCode::Subtract, 0);
+    assert!(matches!(vm.interpret(chunk), Err(LoxError::Compile)));
+
+    // Empty chunks are valid.
+    assert!(vm.interpret(Chunk::new()).unwrap().is_null());
+}
+
+#[test]
+fn test_fuel() {
+    let mut vm = LoxVm::new();
+    vm.set_fuel(3);
+
+    assert!(matches!(vm.interpret(arithmetic_chunk()), Err(LoxError::Interrupted)));
+    assert_eq!(vm.fuel(), Some(0));
+
+    // Without more fuel the VM can't make progress.
+    assert!(matches!(vm.resume(), Err(LoxError::Interrupted)));
+
+    // Two more instructions, and the `Return` appended to the chunk.
+    vm.set_fuel(3);
+    let value = vm.resume().expect("resume failed");
+    assert_eq!(value.as_f64(), Some(7.0));
+    assert_eq!(vm.fuel(), Some(0));
+}
+
+#[test]
+fn test_interrupt_handle() {
+    let mut vm = LoxVm::new();
+    let handle = vm.interrupt_handle();
+
+    thread::spawn(move || handle.interrupt()).join().unwrap();
+    assert!(matches!(vm.interpret(arithmetic_chunk()), Err(LoxError::Interrupted)));
+
+    // The interrupt is cleared once observed.
+    let value = vm.resume().expect("resume failed");
+    assert_eq!(value.as_f64(), Some(7.0));
+}
+
+#[test]
+fn test_resume_after_finish() {
+    let mut vm = LoxVm::new();
+    assert_eq!(vm.resume().unwrap_err(), LoxError::NotResumable);
+
+    // Code after a completed `Return` is never run.
+    let mut chunk = Chunk::new();
+    for value in [1.0, 2.0] {
+        let index = chunk.add_constant(value);
+        chunk.write(OpCode::Constant, 0);
+        chunk.write(index, 0);
+        chunk.write(OpCode::Return, 0);
+    }
+    assert_eq!(vm.interpret(chunk).unwrap().as_f64(), Some(1.0));
+    assert_eq!(vm.resume().unwrap_err(), LoxError::NotResumable);
+    assert_eq!(vm.step().unwrap_err(), LoxError::NotResumable);
+}
+
+#[test]
+fn test_resume_after_error() {
+    let mut vm = LoxVm::new();
+
+    // Negating null fails before 5 is pushed and returned.
+    let mut chunk = Chunk::new();
+    let null = chunk.add_constant(Value::default());
+    chunk.write(OpCode::Constant, 0);
+    chunk.write(null, 0);
+    chunk.write(OpCode::Negate, 0);
+    let five = chunk.add_constant(5.0);
+    chunk.write(OpCode::Constant, 0);
+    chunk.write(five, 0);
+    chunk.write(OpCode::Return, 0);
+
+    assert_eq!(vm.interpret(chunk).unwrap_err(), LoxError::TypeError);
+    assert_eq!(vm.resume().unwrap_err(), LoxError::NotResumable);
+}
+
+/// Builds a chunk that pushes the given numbers, and adds them together.
+fn sum_chunk(count: usize) -> Chunk {
+    let mut chunk = Chunk::new();
+    for i in 0..count {
+        let index = chunk.add_constant(i as f64);
+        chunk.write(OpCode::Constant, 0);
+        chunk.write(index, 0);
+    }
+    for _ in 1..count {
+        chunk.write(OpCode::Add, 0);
+    }
+    chunk
+}
+
+#[test]
+fn test_stack_growth() {
+    let mut vm = LoxVm::with_config(VmConfig {
+        initial_stack: 1,
+        max_stack: 8,
+        ..VmConfig::default()
+    });
+
+    let value = vm.interpret(sum_chunk(8)).expect("interpret failed");
+    assert_eq!(value.as_f64(), Some(28.0));
+
+    assert!(matches!(vm.interpret(sum_chunk(9)), Err(LoxError::StackOverflow)));
+
+    // The VM is still usable after an overflow.
+    let value = vm.interpret(sum_chunk(2)).expect("interpret failed");
+    assert_eq!(value.as_f64(), Some(1.0));
+}
+
+#[test]
+fn test_max_constants() {
+    let mut vm = LoxVm::with_config(VmConfig {
+        max_constants: 2,
+        ..VmConfig::default()
+    });
+
+    assert!(vm.interpret(sum_chunk(2)).is_ok());
+    assert!(matches!(vm.interpret(sum_chunk(3)), Err(LoxError::Compile)));
+}
+
+/// Builds a chunk that evaluates `(1 + 2) * 3`, one operation per line.
+fn multiline_chunk() -> Chunk {
+    let mut chunk = Chunk::new();
+    for (value, line) in [(1.0, 1), (2.0, 1)] {
+        let index = chunk.add_constant(value);
+        chunk.write(OpCode::Constant, line);
+        chunk.write(index, line);
+    }
+    chunk.write(OpCode::Add, 2);
+    let index = chunk.add_constant(3.0);
+    chunk.write(OpCode::Constant, 3);
+    chunk.write(index, 3);
+    chunk.write(OpCode::Multiply, 3);
+    chunk.write(OpCode::Return, 4);
+    chunk
+}
+
+#[test]
+fn test_breakpoints() {
+    let mut vm = LoxVm::new();
+    vm.set_breakpoint(2);
+    vm.set_breakpoint(4);
+
+    assert!(matches!(vm.interpret(multiline_chunk()), Err(LoxError::Paused)));
+    assert_eq!(vm.line(), Some(2));
+    let stack = vm.stack().iter().map(|value| value.as_f64()).collect::<Vec<_>>();
+    assert_eq!(stack, vec![Some(1.0), Some(2.0)]);
+
+    assert!(matches!(vm.resume(), Err(LoxError::Paused)));
+    assert_eq!(vm.line(), Some(4));
+    assert_eq!(vm.stack().len(), 1);
+
+    assert!(vm.clear_breakpoint(2));
+    assert!(!vm.clear_breakpoint(2));
+    assert_eq!(vm.breakpoints().collect::<Vec<_>>(), vec![4]);
+
+    assert_eq!(vm.resume().unwrap().as_f64(), Some(9.0));
+    assert_eq!(vm.line(), None);
+}
+
+#[test]
+fn test_step() {
+    let mut vm = LoxVm::new();
+    vm.set_breakpoint(1);
+    assert!(matches!(vm.interpret(multiline_chunk()), Err(LoxError::Paused)));
+
+    // Each step runs the rest of the current line.
+    let mut lines = vec![vm.line()];
+    while matches!(vm.step(), Err(LoxError::Paused)) {
+        lines.push(vm.line());
+    }
+    assert_eq!(lines, vec![Some(1), Some(2), Some(3), Some(4)]);
+
+    // Stepping doesn't stop later runs without breakpoints.
+    vm.clear_breakpoints();
+    assert_eq!(vm.interpret(multiline_chunk()).unwrap().as_f64(), Some(9.0));
+}
+
+#[test]
+fn test_error_stack() {
+    let mut chunk = Chunk::new();
+    let index = chunk.add_constant(1.0);
+    chunk.write(OpCode::Constant, 1);
+    chunk.write(index, 1);
+    let index = chunk.add_constant(Value::default());
+    chunk.write(OpCode::Constant, 1);
+    chunk.write(index, 1);
+    chunk.write(OpCode::Negate, 1);
+
+    // Stopping with an error keeps the stack for inspection.
+    let mut vm = LoxVm::new();
+    assert!(matches!(vm.interpret(chunk), Err(LoxError::TypeError)));
+    assert_eq!(vm.stack().len(), 1);
+}
@@ -116,7 +116,7 @@ impl Parse for ArrayConstruct {
         };
 
         // Array instantiation
-        let expr_arr = if let Some(Expr::Repeat(expr_arr)) = punc.iter().skip(1).next() {
+        let expr_arr = if let Some(Expr::Repeat(expr_arr)) = punc.iter().nth(1) {
             expr_arr
         } else {
             return Err(syn::Error::new_spanned(
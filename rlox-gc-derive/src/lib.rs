@@ -1,103 +1,307 @@
 use proc_macro::TokenStream;
-use proc_macro2::Literal;
-use quote::quote;
-use syn::{self, parse_macro_input, Data, DeriveInput, Lit};
+use proc_macro2::{Ident, Literal, TokenStream as TokenStream2, TokenTree};
+use quote::{format_ident, quote, ToTokens};
+use syn::{
+    self, parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Field, Fields, GenericParam, Lit, Meta,
+    NestedMeta, Type,
+};
 
-#[proc_macro_derive(Scan)]
+/// Derives `Scan` for structs and enums.
+///
+/// Every field is visited, so each field's type must implement `Scan`. Field
+/// types that use a type parameter get a `Scan` bound in the generated impl,
+/// except those that refer back to the derived type. Recursive generic types
+/// like `Vec<Gc<Node<T>>>` need `T: 'static` on the type itself.
+///
+/// # Attributes
+///
+/// - `#[scan(finalize)]` on the type forwards `Scan::as_finalize` to the type's
+///   `Finalize` implementation.
+/// - `#[scan(skip)]` on a field leaves it out, for data that holds no `Gc<T>`
+///   pointers, like host resources. The field's type doesn't need to implement
+///   `Scan`. Fields that name `Gc` or `GcCell` in their type are rejected.
+/// - `#[scan(unsafe_ignore)]` on a field leaves it out, even though it may hold
+///   `Gc<T>` pointers. The pointers are never unrooted, so they keep their objects
+///   alive until the field is dropped, and cycles through the field are never
+///   collected. The collector can't check what the field holds, so its
+///   correctness is up to the caller.
+#[proc_macro_derive(Scan, attributes(scan))]
 pub fn derive_gc_scan(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
 
+    match expand_scan(ast) {
+        Ok(gen) => gen.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand_scan(mut ast: DeriveInput) -> syn::Result<TokenStream2> {
     let ty = ast.ident.clone();
 
-    let trace_body = match ast.data {
-        Data::Struct(ref data) => {
-            data.fields
-                .iter()
-                .enumerate()
-                .map(|(index, field)| {
-                    match field.ident.clone() {
-                        Some(ident) => {
-                            quote! { self.#ident.scan(ctx); }
-                        }
-                        None => {
-                            // Tuple index
-                            let idx = Lit::new(Literal::usize_unsuffixed(index));
-                            quote! { self.#idx.scan(ctx); }
-                        }
-                    }
-                })
-                .collect::<Vec<_>>()
+    let options = scan_options(&ast.attrs, CONTAINER_OPTIONS)?;
+
+    let finalize = if options.iter().any(|opt| opt == "finalize") {
+        quote! {
+            fn as_finalize(&self) -> Option<&dyn rlox_gc::scan::Finalize> {
+                Some(self)
+            }
         }
-        Data::Enum(_) => todo!(),
-        Data::Union(_) => unimplemented!("Untagged unions not implemented yet"),
+    } else {
+        quote! {}
     };
 
-    let root_body = match ast.data {
+    // Fields that will be visited, used to determine which type parameters need a `Scan` bound.
+    let mut scanned_types = vec![];
+
+    let (trace_body, root_body, unroot_body) = match ast.data {
         Data::Struct(ref data) => {
-            data.fields
-                .iter()
-                .enumerate()
-                .map(|(index, field)| {
-                    match field.ident.clone() {
-                        Some(ident) => {
-                            quote! { self.#ident.root(); }
-                        }
-                        None => {
-                            // Tuple index
-                            let idx = Lit::new(Literal::usize_unsuffixed(index));
-                            quote! { self.#idx.root(); }
-                        }
-                    }
-                })
-                .collect::<Vec<_>>()
+            let fields = ScanFields::new(&data.fields)?;
+            scanned_types.extend(fields.types());
+
+            let pattern = fields.pattern(quote! { Self });
+            let trace_body = fields.visit(quote! { scan(ctx) });
+            let root_body = fields.visit(quote! { root() });
+            let unroot_body = fields.visit(quote! { unroot() });
+
+            (
+                quote! { let #pattern = self; #trace_body },
+                quote! { let #pattern = self; #root_body },
+                quote! { let #pattern = self; #unroot_body },
+            )
         }
-        Data::Enum(_) => todo!(),
-        Data::Union(_) => unimplemented!("Untagged unions not implemented yet"),
-    };
+        Data::Enum(ref data) if data.variants.is_empty() => {
+            // Matching on a reference to an empty enum is not exhaustive, so the value is matched.
+            let body = quote! { match *self {} };
+            (body.clone(), body.clone(), body)
+        }
+        Data::Enum(ref data) => {
+            let mut trace_arms = vec![];
+            let mut root_arms = vec![];
+            let mut unroot_arms = vec![];
 
-    let unroot_body = match ast.data {
-        Data::Struct(ref data) => {
-            data.fields
-                .iter()
-                .enumerate()
-                .map(|(index, field)| {
-                    match field.ident.clone() {
-                        Some(ident) => {
-                            quote! { self.#ident.unroot(); }
-                        }
-                        None => {
-                            // Tuple struct
-                            let idx = Lit::new(Literal::usize_unsuffixed(index));
-                            quote! { self.#idx.unroot(); }
-                        }
-                    }
-                })
-                .collect::<Vec<_>>()
+            for variant in &data.variants {
+                let fields = ScanFields::new(&variant.fields)?;
+                scanned_types.extend(fields.types());
+
+                let name = &variant.ident;
+                let pattern = fields.pattern(quote! { Self::#name });
+                let trace_body = fields.visit(quote! { scan(ctx) });
+                let root_body = fields.visit(quote! { root() });
+                let unroot_body = fields.visit(quote! { unroot() });
+
+                trace_arms.push(quote! { #pattern => { #trace_body } });
+                root_arms.push(quote! { #pattern => { #root_body } });
+                unroot_arms.push(quote! { #pattern => { #unroot_body } });
+            }
+
+            (
+                quote! { match self { #(#trace_arms)* } },
+                quote! { match self { #(#root_arms)* } },
+                quote! { match self { #(#unroot_arms)* } },
+            )
+        }
+        Data::Union(ref data) => {
+            return Err(syn::Error::new_spanned(
+                data.union_token,
+                "Untagged unions not implemented yet",
+            ));
         }
-        Data::Enum(_) => todo!(),
-        Data::Union(_) => unimplemented!("Untagged unions not implemented yet"),
     };
 
+    // Visited fields whose types depend on a type parameter must be `Scan`. Bounding the field
+    // types rather than the parameters leaves it to the `Scan` impls to add bounds like
+    // `T: 'static` for `Gc<T>`.
+    let params = ast
+        .generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Type(param) => Some(param.ident.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let mut bounded: Vec<&Type> = vec![];
+    for field_ty in scanned_types {
+        let tokens = field_ty.to_token_stream();
+        let generic = params.iter().any(|ident| mentions(tokens.clone(), ident));
+        // A bound on a type that refers back to the derived type, like `Vec<Gc<Self>>`, would
+        // require this impl to prove itself, which the compiler reports as overflow.
+        let recursive = mentions(tokens.clone(), &ty) || mentions(tokens.clone(), "Self");
+        let seen = bounded
+            .iter()
+            .any(|other| other.to_token_stream().to_string() == tokens.to_string());
+
+        if generic && !recursive && !seen {
+            bounded.push(field_ty);
+        }
+    }
+    let where_clause = ast.generics.make_where_clause();
+    for ty in bounded {
+        where_clause.predicates.push(parse_quote! { #ty: rlox_gc::scan::Scan });
+    }
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
     let gen = quote! {
-        unsafe impl rlox_gc::scan::Scan for #ty {
+        unsafe impl #impl_generics rlox_gc::scan::Scan for #ty #ty_generics #where_clause {
+            #[allow(unused_variables)]
             fn scan(&self, ctx: &mut rlox_gc::context::Context<'_>) {
                 use rlox_gc::scan::Scan;
-                #(#trace_body)*
+                #trace_body
             }
 
             fn root(&self) {
                 use rlox_gc::scan::Scan;
-                #(#root_body)*
+                #root_body
             }
 
             fn unroot(&self) {
                 use rlox_gc::scan::Scan;
-                #(#unroot_body)*
+                #unroot_body
             }
+
+            #finalize
         }
     };
 
     // println!("{}", gen);
 
-    gen.into()
+    Ok(gen)
+}
+
+/// Fields of a struct or enum variant, with the options set on each.
+struct ScanFields<'a> {
+    fields: &'a Fields,
+    /// Whether each field is visited.
+    scanned: Vec<bool>,
+}
+
+impl<'a> ScanFields<'a> {
+    fn new(fields: &'a Fields) -> syn::Result<Self> {
+        let scanned = fields
+            .iter()
+            .map(|field| {
+                let options = scan_options(&field.attrs, FIELD_OPTIONS)?;
+                let skip = options.iter().any(|opt| opt == "skip");
+                let ignore = options.iter().any(|opt| opt == "unsafe_ignore");
+
+                if skip
+                    && (mentions(field.ty.to_token_stream(), "Gc") || mentions(field.ty.to_token_stream(), "GcCell"))
+                {
+                    return Err(syn::Error::new_spanned(
+                        &field.ty,
+                        "skipped field may hold `Gc` pointers, use `#[scan(unsafe_ignore)]` if this is intended",
+                    ));
+                }
+
+                Ok(!skip && !ignore)
+            })
+            .collect::<syn::Result<Vec<_>>>()?;
+
+        Ok(ScanFields { fields, scanned })
+    }
+
+    /// Types of the visited fields.
+    fn types(&self) -> impl Iterator<Item = &'a Type> + '_ {
+        self.fields
+            .iter()
+            .zip(self.scanned.iter())
+            .filter(|(_, scanned)| **scanned)
+            .map(|(field, _)| &field.ty)
+    }
+
+    /// Binding for a field in a destructuring pattern.
+    fn binding(index: usize, field: &Field) -> Ident {
+        // `format_ident!` strips the `r#` prefix of raw identifiers like `r#type`.
+        match field.ident {
+            Some(ref ident) => format_ident!("__scan_{}", ident),
+            None => format_ident!("__scan_{}", index),
+        }
+    }
+
+    /// Pattern that destructures the fields into bindings. Fields that aren't visited are ignored.
+    fn pattern(&self, path: TokenStream2) -> TokenStream2 {
+        let bindings = self
+            .fields
+            .iter()
+            .enumerate()
+            .zip(self.scanned.iter())
+            .map(|((index, field), scanned)| {
+                let binding = if *scanned {
+                    Self::binding(index, field).into_token_stream()
+                } else {
+                    quote! { _ }
+                };
+
+                match field.ident {
+                    Some(ref ident) => quote! { #ident: #binding },
+                    None => {
+                        // Tuple index
+                        let idx = Lit::new(Literal::usize_unsuffixed(index));
+                        quote! { #idx: #binding }
+                    }
+                }
+            });
+
+        quote! { #path { #(#bindings),* } }
+    }
+
+    /// Calls the given method on every visited field.
+    fn visit(&self, method: TokenStream2) -> TokenStream2 {
+        let calls = self
+            .fields
+            .iter()
+            .enumerate()
+            .zip(self.scanned.iter())
+            .filter(|(_, scanned)| **scanned)
+            .map(|((index, field), _)| {
+                let binding = Self::binding(index, field);
+                quote! { #binding.#method; }
+            });
+
+        quote! { #(#calls)* }
+    }
+}
+
+/// Checks whether the tokens contain the given identifier.
+fn mentions<I>(tokens: TokenStream2, ident: &I) -> bool
+where
+    I: ?Sized,
+    Ident: PartialEq<I>,
+{
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(ref other) => other == ident,
+        TokenTree::Group(group) => mentions(group.stream(), ident),
+        _ => false,
+    })
+}
+
+/// Options accepted by `#[scan(...)]` on the type being derived.
+const CONTAINER_OPTIONS: &[&str] = &["finalize"];
+
+/// Options accepted by `#[scan(...)]` on a field.
+const FIELD_OPTIONS: &[&str] = &["skip", "unsafe_ignore"];
+
+/// Collects the options set in `#[scan(...)]` attributes.
+///
+/// Returns an error when an option is not in the `allowed` list.
+fn scan_options(attrs: &[Attribute], allowed: &[&str]) -> syn::Result<Vec<String>> {
+    let mut options = vec![];
+
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("scan")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => {
+                for nested in list.nested.iter() {
+                    match nested {
+                        NestedMeta::Meta(Meta::Path(path)) if allowed.iter().any(|opt| path.is_ident(opt)) => {
+                            options.push(path.get_ident().unwrap().to_string());
+                        }
+                        _ => return Err(syn::Error::new_spanned(nested, "unknown scan option")),
+                    }
+                }
+            }
+            meta => return Err(syn::Error::new_spanned(meta, "expected `#[scan(...)]`")),
+        }
+    }
+
+    Ok(options)
 }
@@ -1 +1,345 @@
+//! Size-classed arena that owns the memory of every `GcBox`.
+//!
+//! Small objects are packed into fixed size pages. Each page is divided into
+//! slots of equal size, and an object is placed in the smallest size class
+//! that can fit it. Freed slots are kept on a free list per size class, and
+//! reused before the page's bump region is consumed.
+//!
+//! Objects too large for any size class are allocated individually.
+//!
+//! The arena accounts for the memory it reserves rather than the size of the
+//! objects: a whole page is charged when it's allocated, and pages are kept
+//! for reuse once their objects are freed. Memory owned by the objects
+//! themselves, like the buffer of a `Vec<T>`, isn't seen by the arena.
+use crate::{gc::GcBox, scan::Scan};
+use std::{
+    alloc::{self, Layout},
+    collections::HashMap,
+    ptr::{self, NonNull},
+};
 
+/// Size in bytes of a page. Pages are also aligned to this size.
+const PAGE_SIZE: usize = 16 * 1024;
+
+/// Slot size of the smallest size class.
+const MIN_SLOT_SIZE: usize = 16;
+
+/// Number of size classes. Each class doubles the slot size of the previous one.
+const CLASS_COUNT: usize = 7;
+
+/// Slot size of the largest size class.
+const MAX_SLOT_SIZE: usize = MIN_SLOT_SIZE << (CLASS_COUNT - 1);
+
+/// Entry in a page's slot table. `None` when the slot is free.
+type Slot = Option<NonNull<GcBox<dyn Scan>>>;
+
+pub(crate) struct Arena {
+    classes: Vec<SizeClass>,
+    /// Objects that don't fit in a size class.
+    large: Vec<NonNull<GcBox<dyn Scan>>>,
+    /// Position in `large` by the address of the object.
+    large_lookup: HashMap<usize, usize>,
+    /// Number of live objects.
+    len: usize,
+    /// Size in bytes of the memory reserved for objects.
+    bytes: usize,
+}
+
+impl Arena {
+    pub(crate) fn new() -> Self {
+        Arena {
+            classes: (0..CLASS_COUNT).map(|i| SizeClass::new(MIN_SLOT_SIZE << i)).collect(),
+            large: vec![],
+            large_lookup: HashMap::new(),
+            len: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Number of live objects in the arena.
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Size in bytes of the memory reserved for objects: every page, plus the large objects.
+    #[inline]
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Number of bytes the arena would have to reserve to allocate an object with the given layout.
+    ///
+    /// This is zero when a page has a slot available.
+    pub(crate) fn reserve_size(&self, layout: Layout) -> usize {
+        match Self::class_index(layout) {
+            Some(index) if self.classes[index].has_space() => 0,
+            Some(_) => PAGE_SIZE,
+            None => layout.size(),
+        }
+    }
+
+    /// Moves the given box into the arena.
+    pub(crate) fn alloc<T: 'static + Scan>(&mut self, gc_box: GcBox<T>) -> NonNull<GcBox<T>> {
+        let layout = Layout::new::<GcBox<T>>();
+        self.len += 1;
+        self.bytes += self.reserve_size(layout);
+
+        match Self::class_index(layout) {
+            Some(index) => {
+                let (slot, raw) = self.classes[index].reserve();
+                let ptr = raw.cast::<GcBox<T>>();
+                // SAFETY: The slot is large enough and aligned for the layout, because
+                //         slots are a power of two in size, at least as large as the layout,
+                //         and pages are aligned to a multiple of the slot size.
+                unsafe { ptr::write(ptr.as_ptr(), gc_box) };
+                *slot = Some(ptr);
+                ptr
+            }
+            None => {
+                let ptr = unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(gc_box))) };
+                self.large_lookup
+                    .insert(ptr.as_ptr() as *mut u8 as usize, self.large.len());
+                self.large.push(ptr);
+                ptr
+            }
+        }
+    }
+
+    /// Calls the given function for every live object, in heap order.
+    pub(crate) fn for_each(&self, mut f: impl FnMut(NonNull<GcBox<dyn Scan>>)) {
+        for class in &self.classes {
+            for page in &class.pages {
+                for ptr in page.slots[..page.bump].iter().flatten() {
+                    f(*ptr);
+                }
+            }
+        }
+
+        for ptr in &self.large {
+            f(*ptr);
+        }
+    }
+
+    /// Walks every live object in heap order, and deallocates those for which the
+    /// given predicate returns `false`.
+    ///
+    /// # Safety
+    ///
+    /// Deallocated objects must not be reachable by any other object or `Gc<T>`.
+    pub(crate) unsafe fn retain(&mut self, mut f: impl FnMut(NonNull<GcBox<dyn Scan>>) -> bool) {
+        let mut condemned = vec![];
+        self.for_each(|ptr| {
+            if !f(ptr) {
+                condemned.push(ptr);
+            }
+        });
+        self.free(&condemned);
+    }
+
+    /// Deallocates the given objects.
+    ///
+    /// Every value is dropped before any memory is released, because dropping a value
+    /// decrements the root count of the objects it points to, which may be freed too.
+    ///
+    /// # Safety
+    ///
+    /// The objects must have been allocated by this arena, and must not be
+    /// reachable by any other object or `Gc<T>`.
+    pub(crate) unsafe fn free(&mut self, ptrs: &[NonNull<GcBox<dyn Scan>>]) {
+        let layouts = ptrs
+            .iter()
+            .map(|ptr| Layout::for_value(ptr.as_ref()))
+            .collect::<Vec<_>>();
+        for ptr in ptrs {
+            ptr::drop_in_place(ptr::addr_of_mut!((*ptr.as_ptr()).value));
+        }
+        for (ptr, layout) in ptrs.iter().zip(layouts) {
+            self.release(*ptr, layout);
+        }
+    }
+
+    /// Releases the memory of an object whose value has been dropped.
+    unsafe fn release(&mut self, ptr: NonNull<GcBox<dyn Scan>>, layout: Layout) {
+        self.len -= 1;
+
+        match Self::class_index(layout) {
+            Some(index) => {
+                let class = &mut self.classes[index];
+                let addr = ptr.as_ptr() as *mut u8 as usize;
+                // Pages are aligned to their size, so the page base can be found from any address inside it.
+                let base = addr & !(PAGE_SIZE - 1);
+                let page_index = class.lookup[&base];
+                let slot_index = (addr - base) / class.slot_size;
+
+                #[cfg(feature = "verify-heap")]
+                crate::verify::poison(ptr.as_ptr() as *mut u8, class.slot_size);
+
+                class.pages[page_index as usize].slots[slot_index] = None;
+                class.free.push((page_index, slot_index as u32));
+            }
+            None => {
+                let position = self
+                    .large_lookup
+                    .remove(&(ptr.as_ptr() as *mut u8 as usize))
+                    .expect("object not allocated by arena");
+                self.bytes -= layout.size();
+                self.large.swap_remove(position);
+                // The last object took the place of the removed one.
+                if let Some(moved) = self.large.get(position) {
+                    self.large_lookup.insert(moved.as_ptr() as *mut u8 as usize, position);
+                }
+
+                #[cfg(feature = "verify-heap")]
+                crate::verify::poison(ptr.as_ptr() as *mut u8, layout.size());
+
+                alloc::dealloc(ptr.as_ptr() as *mut u8, layout);
+            }
+        }
+    }
+
+    /// Checks whether the object was allocated by this arena, and is still live.
+    pub(crate) fn contains(&self, ptr: NonNull<GcBox<dyn Scan>>) -> bool {
+        // The object may belong to another arena, but is kept alive by the `Gc<T>` pointing to it.
+        let layout = Layout::for_value(unsafe { ptr.as_ref() });
+
+        match Self::class_index(layout) {
+            Some(index) => {
+                let class = &self.classes[index];
+                let addr = ptr.as_ptr() as *mut u8 as usize;
+                let base = addr & !(PAGE_SIZE - 1);
+                class.lookup.get(&base).is_some_and(|page_index| {
+                    let slot = class.pages[*page_index as usize].slots[(addr - base) / class.slot_size];
+                    slot.is_some_and(|slot| ptr::addr_eq(slot.as_ptr(), ptr.as_ptr()))
+                })
+            }
+            None => self.large_lookup.contains_key(&(ptr.as_ptr() as *mut u8 as usize)),
+        }
+    }
+
+    /// Determines the size class for the given layout.
+    ///
+    /// Returns `None` when the layout is too large to be packed into a page.
+    #[inline]
+    fn class_index(layout: Layout) -> Option<usize> {
+        let size = layout.size().max(layout.align()).max(MIN_SLOT_SIZE).next_power_of_two();
+        if size <= MAX_SLOT_SIZE {
+            Some((size / MIN_SLOT_SIZE).trailing_zeros() as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        // Remaining objects are dropped without any regard for the pointers between
+        // them. The collector is responsible for ensuring the arena is empty when dropped.
+        unsafe { self.retain(|_| false) };
+    }
+}
+
+/// Pages holding objects of the same slot size.
+struct SizeClass {
+    slot_size: usize,
+    pages: Vec<Page>,
+    /// Freed slots, as page and slot indices.
+    free: Vec<(u32, u32)>,
+    /// Page index by the address of the page's memory.
+    lookup: HashMap<usize, u32>,
+}
+
+impl SizeClass {
+    fn new(slot_size: usize) -> Self {
+        SizeClass {
+            slot_size,
+            pages: vec![],
+            free: vec![],
+            lookup: HashMap::new(),
+        }
+    }
+
+    /// Checks whether a slot can be reserved without allocating a new page.
+    fn has_space(&self) -> bool {
+        !self.free.is_empty() || self.pages.last().is_some_and(Page::has_space)
+    }
+
+    /// Finds an empty slot, allocating a new page if the class is full.
+    ///
+    /// Returns the slot's entry in the page table, and a pointer to its memory.
+    fn reserve(&mut self) -> (&mut Slot, NonNull<u8>) {
+        let (page_index, slot_index) = match self.free.pop() {
+            Some((page_index, slot_index)) => (page_index as usize, slot_index as usize),
+            None => {
+                let has_space = self.pages.last().map(Page::has_space).unwrap_or(false);
+                if !has_space {
+                    let page = Page::new(self.slot_size);
+                    self.lookup.insert(page.base.as_ptr() as usize, self.pages.len() as u32);
+                    self.pages.push(page);
+                }
+                let page_index = self.pages.len() - 1;
+                let page = &mut self.pages[page_index];
+                page.bump += 1;
+                (page_index, page.bump - 1)
+            }
+        };
+
+        let page = &mut self.pages[page_index];
+        let raw = unsafe { NonNull::new_unchecked(page.base.as_ptr().add(slot_index * self.slot_size)) };
+        (&mut page.slots[slot_index], raw)
+    }
+}
+
+/// Contiguous block of memory divided into equally sized slots.
+struct Page {
+    base: NonNull<u8>,
+    /// Pointer to the object occupying each slot, kept so the objects can be
+    /// dropped through their vtable.
+    slots: Box<[Slot]>,
+    /// Number of slots that have been handed out from the start of the page.
+    bump: usize,
+}
+
+impl Page {
+    fn layout() -> Layout {
+        // Page size is a non-zero power of two.
+        Layout::from_size_align(PAGE_SIZE, PAGE_SIZE).unwrap()
+    }
+
+    fn new(slot_size: usize) -> Self {
+        let base = unsafe { alloc::alloc(Self::layout()) };
+        let base = NonNull::new(base).unwrap_or_else(|| alloc::handle_alloc_error(Self::layout()));
+
+        Page {
+            base,
+            slots: vec![None; PAGE_SIZE / slot_size].into_boxed_slice(),
+            bump: 0,
+        }
+    }
+
+    #[inline]
+    fn has_space(&self) -> bool {
+        self.bump < self.slots.len()
+    }
+}
+
+impl Drop for Page {
+    fn drop(&mut self) {
+        // Objects are dropped by the arena, this only releases the memory.
+        unsafe { alloc::dealloc(self.base.as_ptr(), Self::layout()) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_class_index() {
+        assert_eq!(Arena::class_index(Layout::new::<u8>()), Some(0));
+        assert_eq!(Arena::class_index(Layout::new::<[u8; 16]>()), Some(0));
+        assert_eq!(Arena::class_index(Layout::new::<[u8; 17]>()), Some(1));
+        assert_eq!(Arena::class_index(Layout::new::<[u8; 1024]>()), Some(6));
+        assert_eq!(Arena::class_index(Layout::new::<[u8; 1025]>()), None);
+    }
+}
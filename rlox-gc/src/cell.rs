@@ -1,7 +1,157 @@
-//! TODO: Scan is not implemented for `RefCell` or `Cell` on purpose.
-//!       Interior mutability breaks the invariants of the garbage
-//!       collector if we can move a `Gc<T>` out of another `Gc<T>`
-//!       without marking it as a root.
-//!       We must thus implement our own `GcCell` if we want to offer
-//!       interior mutability to users.
-//!       See: https://manishearth.github.io/blog/2015/09/01/designing-a-gc-in-rust/
+//! Interior mutability for values in the garbage collector.
+//!
+//! Scan is not implemented for `RefCell` or `Cell` on purpose.
+//! Interior mutability breaks the invariants of the garbage
+//! collector if we can move a `Gc<T>` out of another `Gc<T>`
+//! without marking it as a root.
+//!
+//! Borrowing a `GcCell` mutably roots its contents until the collector's next
+//! cycle starts, so pointers moved out of the cell remain part of the root set.
+//! The contents are only rooted by the first borrow after a cycle, so repeated
+//! mutation doesn't walk them again.
+//!
+//! See: https://manishearth.github.io/blog/2015/09/01/designing-a-gc-in-rust/
+use crate::{context::Context, scan::Scan, Collector, Gc};
+use std::{
+    cell::{Cell, Ref, RefCell, RefMut},
+    fmt::{self, Debug},
+    ops::{Deref, DerefMut},
+};
+
+/// Mutable memory location that can live in the garbage collector.
+///
+/// Mutable access to a cell inside the collector must go through
+/// [`Gc::borrow_mut`](struct.Gc.html#method.borrow_mut), which informs
+/// the collector of the write.
+pub struct GcCell<T: Scan> {
+    /// Whether the contents are part of the root set, which is the
+    /// case while the cell lives outside of the collector.
+    rooted: Cell<bool>,
+    value: RefCell<T>,
+}
+
+impl<T: Scan> GcCell<T> {
+    pub fn new(value: T) -> Self {
+        GcCell {
+            rooted: Cell::new(true),
+            value: RefCell::new(value),
+        }
+    }
+
+    /// Immutably borrows the wrapped value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently mutably borrowed.
+    #[inline]
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.value.borrow()
+    }
+
+    /// Returns a mutable reference to the wrapped value.
+    ///
+    /// Exclusive access means the cell is not in the collector, so no write barrier is needed.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: Scan + 'static> Gc<GcCell<T>> {
+    /// Mutably borrows the value in the cell.
+    ///
+    /// The collector is needed to record the write in its remembered set
+    /// when the cell belongs to the old generation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell doesn't belong to the given collector, or if the value
+    /// is currently borrowed. With debug assertions or the `verify-heap` feature,
+    /// also panics when the borrow ends if a `Gc<T>` belonging to a different
+    /// collector was stored in the cell.
+    pub fn borrow_mut<'a>(gc: &'a Gc<GcCell<T>>, collector: &'a Collector) -> GcCellRefMut<'a, T> {
+        // Stores are checked against this collector, and recorded in its remembered set.
+        assert!(collector.owns(gc.ptr), "GcCell belongs to a different collector");
+        collector.write_barrier(gc.ptr);
+
+        let value = gc.value.borrow_mut();
+        // Contents of a cell inside the collector are not roots. Pointers can be moved
+        // out of the cell while it's borrowed, so they stay rooted until the next cycle.
+        if !gc.rooted.get() {
+            value.root();
+            gc.rooted.set(true);
+            collector.mark_dirty(gc.ptr);
+        }
+
+        GcCellRefMut {
+            #[cfg(any(debug_assertions, feature = "verify-heap"))]
+            collector,
+            value,
+        }
+    }
+}
+
+unsafe impl<T: Scan> Scan for GcCell<T> {
+    #[inline]
+    fn scan(&self, ctx: &mut Context<'_>) {
+        self.value.borrow().scan(ctx);
+    }
+
+    #[inline]
+    fn root(&self) {
+        self.rooted.set(true);
+        self.value.borrow().root();
+    }
+
+    #[inline]
+    fn unroot(&self) {
+        self.rooted.set(false);
+        self.value.borrow().unroot();
+    }
+}
+
+impl<T: Debug + Scan> Debug for GcCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GcCell").field("value", &self.value).finish()
+    }
+}
+
+/// Mutable borrow of the value in a [`GcCell`].
+pub struct GcCellRefMut<'a, T: Scan> {
+    /// Collector owning the cell, which must also own everything stored in it.
+    #[cfg(any(debug_assertions, feature = "verify-heap"))]
+    collector: &'a Collector,
+    value: RefMut<'a, T>,
+}
+
+impl<'a, T: Scan> Deref for GcCellRefMut<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<'a, T: Scan> DerefMut for GcCellRefMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.value
+    }
+}
+
+/// Checking the stored pointers walks the whole cell, so it's skipped in release builds.
+#[cfg(any(debug_assertions, feature = "verify-heap"))]
+impl<'a, T: Scan> Drop for GcCellRefMut<'a, T> {
+    fn drop(&mut self) {
+        // Panicking again while unwinding would abort.
+        if !std::thread::panicking() && !self.collector.owns_all(&*self.value) {
+            // Rooted once more, the contents stay rooted after the next cycle starts.
+            // This leaks them instead of leaving a dangling pointer.
+            self.value.root();
+            panic!("Gc pointer belongs to a different collector");
+        }
+    }
+}
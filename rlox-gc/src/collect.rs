@@ -1,47 +1,138 @@
+#[cfg(feature = "verify-heap")]
+use crate::verify::{self, ObjectInfo};
 use crate::{
+    arena::Arena,
+    config::CollectorConfig,
     context::Context,
+    error::AllocError,
     gc::{Gc, GcBox, GcColor},
     scan::Scan,
+    snapshot::{HeapSnapshot, ObjectSnapshot},
+    stats::CollectStats,
 };
-use std::{cell::Cell, ptr::NonNull};
+use std::{
+    alloc::Layout,
+    cell::{Cell, RefCell},
+    mem,
+    ptr::NonNull,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+/// Id given to the next collector.
+static NEXT_HEAP: AtomicU32 = AtomicU32::new(1);
 
+/// Garbage collected heap.
+///
+/// Dropping the collector runs the finalizers of its remaining objects and
+/// deallocates them. When the thread is panicking the heap is leaked instead,
+/// without running any finalizers or destructors.
 pub struct Collector {
-    // TODO: Packed arena
-    // arena: typed_arena::Arena<NonNull<GcBox<dyn Scan>>>,
-    /// For development we're using a linked list.
-    head: Option<NonNull<GcBox<dyn Scan>>>,
+    config: CollectorConfig,
+    stats: CollectStats,
+
+    /// Identifies the objects allocated by this collector.
+    heap: u32,
+    /// Packed storage of all allocated objects.
+    arena: Arena,
+    /// Id given to the next allocated object.
+    next_id: u64,
     state: CollectState,
+    /// Whether the current cycle collects only the young generation.
+    minor: bool,
 
-    /// Temporary head used for sweep pahse.
-    sweep: Option<NonNull<GcBox<dyn Scan>>>,
-    sweep_prev: Option<NonNull<GcBox<dyn Scan>>>,
+    /// Objects in the young generation, in allocation order.
+    young: Vec<NonNull<GcBox<dyn Scan>>>,
+    /// Old objects that may point to young objects, because they have been
+    /// written to, or were promoted while holding young objects.
+    remembered: RefCell<Vec<NonNull<GcBox<dyn Scan>>>>,
+    /// Cells mutably borrowed since the last cycle. Their contents are rooted
+    /// until the next cycle starts.
+    dirty: RefCell<Vec<NonNull<GcBox<dyn Scan>>>>,
 
-    /// Temporary head used for wake phase.
-    wake: Option<NonNull<GcBox<dyn Scan>>>,
+    /// Whether unreachable objects with finalizers have been resurrected during the current cycle.
+    resurrected: bool,
 
     /// Queue of gray objects that need to be scanned.
     gray: Vec<NonNull<GcBox<dyn Scan>>>,
     gray_new: Vec<NonNull<GcBox<dyn Scan>>>,
+
+    /// Queue of unreachable objects with finalizers that need to be run after sweep.
+    finalize: Vec<NonNull<GcBox<dyn Scan>>>,
 }
 
 impl Collector {
     pub fn new() -> Self {
+        Self::with_config(CollectorConfig::default())
+    }
+
+    pub fn with_config(config: CollectorConfig) -> Self {
         Self {
-            // arena: ...,
-            head: None,
+            config,
+            stats: CollectStats::default(),
+
+            heap: NEXT_HEAP.fetch_add(1, Ordering::Relaxed),
+            arena: Arena::new(),
+            next_id: 1,
             state: CollectState::Sleep,
+            minor: false,
 
-            sweep: None,
-            sweep_prev: None,
+            young: vec![],
+            remembered: RefCell::new(vec![]),
+            dirty: RefCell::new(vec![]),
 
-            wake: None,
+            resurrected: false,
 
             gray: vec![],
             gray_new: vec![],
+
+            finalize: vec![],
         }
     }
 
+    /// Moves the value into the collector.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value holds a `Gc<T>` that belongs to a different collector,
+    /// or if the allocation would exceed the configured heap limit.
+    /// See [`try_alloc`](#method.try_alloc) for a fallible version.
     pub fn alloc<T: 'static + Scan>(&mut self, value: T) -> Gc<T> {
+        match self.try_alloc(value) {
+            Ok(gc) => gc,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Moves the value into the collector, unless that would exceed
+    /// [`CollectorConfig::max_heap_bytes`](struct.CollectorConfig.html#structfield.max_heap_bytes).
+    ///
+    /// When the limit would be exceeded, a full collection is run first. If that
+    /// doesn't free enough memory, the value is dropped and an error is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value holds a `Gc<T>` that belongs to a different collector.
+    pub fn try_alloc<T: 'static + Scan>(&mut self, value: T) -> Result<Gc<T>, AllocError> {
+        self.check_owned(&value);
+
+        if let Some(limit) = self.config.max_heap_bytes {
+            let layout = Layout::new::<GcBox<T>>();
+            if self.arena.bytes() + self.arena.reserve_size(layout) > limit {
+                // The value is still rooted, so anything it points to survives.
+                self.collect();
+
+                // Freed slots can be reused, in which case nothing needs to be reserved.
+                let size = self.arena.reserve_size(layout);
+                if self.arena.bytes() + size > limit {
+                    return Err(AllocError {
+                        size,
+                        heap_bytes: self.arena.bytes(),
+                        limit,
+                    });
+                }
+            }
+        }
+
         // When a value containing a `Gc<T>` moves into another `Gc<T>`, we
         // need to unroot the child pointer and all its contents.
         //
@@ -49,61 +140,83 @@ impl Collector {
         // as it will be reachable from the new root.
         value.unroot();
 
+        let id = self.next_id;
+        self.next_id += 1;
+
         let sized = GcBox {
+            id,
+            heap: self.heap,
             // Because we return a `Gc<T>` that we lose track
             // of, we must consider it part of the root set.
             root: Cell::new(1),
-            // We color the new box white, but a sweep may be in progress!
-            // However this is safe because we're inserting into the head
-            // of the linked list. The sweep phase would have started at
-            // the old head, which is now the next pointer.
-            //
-            // Important consideration when moving to a packed arena, we may
-            // be sweeping the arena and deallocating items colored white. The
-            // future solution depends on how the packing will be implemented.
+            // A collection cycle runs to completion within `collect`, so
+            // a new box can't be swept before it's been considered by the
+            // wake phase.
             color: Cell::new(GcColor::White),
-            next: Cell::new(self.head),
+            finalized: Cell::new(false),
+            // New objects start out in the young generation.
+            age: Cell::new(0),
+            remembered: Cell::new(false),
             value,
         };
-        let ptr = unsafe { NonNull::new_unchecked(Box::leak(Box::new(sized))) };
-        self.head = Some(ptr);
+        let ptr = self.arena.alloc(sized);
+        self.young.push(ptr);
         // println!("Alloc {:?}", ptr);
 
-        // Allocation can occur while the sweep phase is in progress.
-        //
-        // If the previously swept pointer is `None`, it means the sweep phase
-        // is currently looking at the head node of the sweep list, which we just changed to
-        // a newly allocated node.
-        //
-        // To enforce the invariant and let the assumptions of the sweep algorithm hold true, we
-        // need to let the sweep know the head is now in the previous position.
-        if self.state == CollectState::Sweep && self.sweep_prev.is_none() {
-            self.sweep_prev = self.head;
-        }
-
-        // Initial state is that object immediately reachable from the root set are marked gray.
-        // self.gray.push(ptr);
-
         // SAFETY: We trust the arena won't give us a bad reference, so we assume it's not null.
         //         By converting a pointer we're detaching the reference from the arena's lifetime, but it will be
         //         kept in the reference counted `Gc<T>` pointer.  The arena is only dropped when all `Gc<T>`
         //         pointers are collected.
-        Gc::from_inner(ptr)
+        Ok(Gc::from_inner(ptr))
     }
 
-    /// Returns the number of objects that have been allocated.
+    /// Checks whether the object pointed to was allocated by this collector.
+    pub fn contains<T: 'static + Scan>(&self, gc: &Gc<T>) -> bool {
+        self.arena.contains(gc.ptr)
+    }
+
+    /// Panics when the value holds a pointer to an object that belongs to a different collector.
     ///
-    /// This is an expensive call that needs to iterate over the entire contents of the inner storage.
+    /// Collectors don't know about each other's objects, so a pointer across heaps
+    /// would be left dangling once the other collector frees its object.
+    pub(crate) fn check_owned(&self, value: &dyn Scan) {
+        if !self.owns_all(value) {
+            panic!("Gc pointer belongs to a different collector");
+        }
+    }
+
+    /// Checks whether every pointer held by the value points into this collector.
+    pub(crate) fn owns_all(&self, value: &dyn Scan) -> bool {
+        let mut edges = vec![];
+        let mut ctx = Context {
+            gray: &mut edges,
+            minor: false,
+            inspect: true,
+        };
+        value.scan(&mut ctx);
+
+        edges.iter().all(|ptr| self.owns(*ptr))
+    }
+
+    /// Checks whether the object was allocated by this collector, by comparing the id in its header.
+    #[inline]
+    pub(crate) fn owns(&self, ptr: NonNull<GcBox<dyn Scan>>) -> bool {
+        unsafe { ptr.as_ref() }.heap == self.heap
+    }
+
+    /// Returns the number of objects that have been allocated.
     #[inline]
     pub fn len(&self) -> usize {
-        let mut head = self.head;
-        let mut count = 0;
-        while let Some(ptr) = head {
-            let gc_box = unsafe { ptr.as_ref() };
-            count += 1;
-            head = gc_box.next.get();
-        }
-        count
+        self.arena.len()
+    }
+
+    /// Returns the size in bytes of the memory reserved for objects.
+    ///
+    /// Small objects are packed into pages, which are counted in full and kept after their
+    /// objects are freed. Memory owned by the objects, like the buffer of a `Vec<T>`, isn't counted.
+    #[inline]
+    pub fn heap_bytes(&self) -> usize {
+        self.arena.bytes()
     }
 
     #[inline(always)]
@@ -111,38 +224,99 @@ impl Collector {
         self.len() == 0
     }
 
-    /// Run a garbage collection cycle.
+    /// Returns the number of objects in the young generation.
+    #[inline]
+    pub fn young_len(&self) -> usize {
+        self.young.len()
+    }
+
+    #[inline]
+    pub fn config(&self) -> &CollectorConfig {
+        &self.config
+    }
+
+    #[inline]
+    pub fn stats(&self) -> &CollectStats {
+        &self.stats
+    }
+
+    /// Run a garbage collection cycle over the whole heap.
+    ///
+    /// Unreachable objects with a finalizer are finalized at the end of the cycle,
+    /// and deallocated by the following cycle.
     pub fn collect(&mut self) {
+        self.minor = false;
+        self.stats.full_collections += 1;
+        self.run_cycle();
+    }
+
+    /// Run a garbage collection cycle over the young generation only.
+    ///
+    /// Old objects are not marked or swept. Young objects that are only
+    /// reachable from old objects are kept alive by the remembered set.
+    pub fn collect_minor(&mut self) {
+        self.minor = true;
+        self.stats.minor_collections += 1;
+        self.run_cycle();
+    }
+
+    fn run_cycle(&mut self) {
         // println!("Collect");
         // TODO: Wake on allocate
         self.state = CollectState::Wake;
-        self.wake = self.head;
+        self.resurrected = false;
+
+        // Contents of the cells borrowed since the last cycle are reachable through the cells again.
+        for ptr in self.dirty.get_mut().drain(..) {
+            // SAFETY: Objects are only freed by a sweep, so the cells are still allocated.
+            unsafe { ptr.as_ref() }.value.unroot();
+        }
 
         loop {
             match self.state {
                 CollectState::Wake => {
                     // All roots must be set to gray.
-                    if let Some(ptr) = self.wake {
+                    let gray = &mut self.gray;
+                    let wake = |ptr: NonNull<GcBox<dyn Scan>>| {
                         let gc_box = unsafe { ptr.as_ref() };
-                        self.wake = gc_box.next.get();
 
                         // A `GcBox` is considered part of the root set if
                         // its reference count is not zero.
                         if gc_box.is_root() {
                             // println!("Root discovered {:?}", ptr);
                             gc_box.color.set(GcColor::Gray);
-                            self.gray.push(ptr);
+                            gray.push(ptr);
+                        }
+                    };
+
+                    if self.minor {
+                        self.young.iter().copied().for_each(wake);
+
+                        // Old objects are not marked during a minor collection, so the
+                        // young objects they point to are discovered via the remembered set.
+                        let mut ctx = Context {
+                            gray: &mut self.gray_new,
+                            minor: true,
+                            inspect: false,
+                        };
+                        for ptr in self.remembered.borrow().iter() {
+                            unsafe { ptr.as_ref() }.value.scan(&mut ctx);
                         }
+                        self.gray.append(ctx.gray);
                     } else {
-                        // All roots have been considered.
-                        self.state = CollectState::Mark;
+                        self.arena.for_each(wake);
                     }
+
+                    // All roots have been considered.
+                    self.state = CollectState::Mark;
                 }
                 CollectState::Mark => {
                     // println!("gray {:?}", self.gray);
 
                     let mut ctx = Context {
                         gray: &mut self.gray_new,
+                        minor: self.minor,
+                        inspect: false,
                     };
                     if let Some(ptr) = self.gray.pop() {
                         let gc_box = unsafe { ptr.as_ref() };
@@ -151,89 +325,336 @@ impl Collector {
                         gc_box.color.set(GcColor::Black);
 
                         // Reachable items have been set from white to gray.
-                        self.gray.extend(ctx.gray.drain(..));
+                        self.gray.append(ctx.gray);
+                    } else if !self.resurrected {
+                        // Before anything is deallocated, unreachable objects with
+                        // finalizers must be kept alive, along with everything they
+                        // can reach.
+                        self.state = CollectState::Resurrect;
                     } else {
+                        #[cfg(feature = "verify-heap")]
+                        self.verify_mark();
+
                         // println!("Preparing for sweep");
                         self.state = CollectState::Sweep;
-                        // Prepare for sweep phase.
-                        self.sweep = self.head;
                     }
                 }
-                CollectState::Sweep => {
-                    if let Some(sweep_ptr) = self.sweep {
-                        // SAFETY: We need to be careful in the `Sweep` phase not to take
-                        //         the `GcBox` as a reference/borrow and keep it on the stack.
-                        //         The raw pointer will soon be deallocated turning the
-                        //         would be reference invalid and violating Rust's invariants.
-                        //         If we use that hypothetical reference after the `Box` drop
-                        //         then we're in for a bad time.
-                        let next_ptr = unsafe { sweep_ptr.as_ref().next.get() };
-                        self.sweep = next_ptr;
-
-                        let color = unsafe { sweep_ptr.as_ref().color.get() };
-                        match color {
-                            GcColor::White => {
-                                // println!("Deallocate {:?}", sweep_ptr);
-
-                                match self.sweep_prev {
-                                    Some(sweep_prev) => {
-                                        // println!("De-link {:?}", sweep_ptr);
-                                        // If the previously swept pointer is `Some` then
-                                        // we are in the middle of the linked list, and the current
-                                        // node needs to be delinked.
-                                        let gc_box_prev = unsafe { sweep_prev.as_ref() };
-                                        gc_box_prev.next.set(next_ptr);
-                                    }
-                                    None => {
-                                        // println!("De-link Head {:?}", sweep_ptr);
-                                        // If the previously swept pointer is `None` then
-                                        // we are looking at the head of the linked list.
-                                        // The head pointer needs to be advanced to the next
-                                        // node in the list.
-                                        assert_eq!(self.head, Some(sweep_ptr));
-                                        self.head = next_ptr;
-                                        // println!("New Head {:?}", self.head);
-                                    }
-                                }
-                                // Cast the pointer to a box and let it drop.
-                                // SAFETY: If all the invariants of the collector hold true, we can safely
-                                //         drop this `GcBox`. Any pointer remaining in a Gc<T>, Vec<_> or
-                                //         linked list, is a bug in the collector.
-                                debug_assert_eq!(
-                                    unsafe { sweep_ptr.as_ref().root.get() },
-                                    0,
-                                    "GcBox deallocated but still rooted."
-                                );
-                                unsafe {
-                                    drop(Box::from_raw(sweep_ptr.as_ptr()));
-                                }
-                            }
-                            GcColor::Black => {
-                                // println!("Survive {:?}", sweep_ptr);
-                                self.sweep_prev = Some(sweep_ptr);
-
-                                // Reachable from root set.
-                                // We change it back to white in preparation for the next mark-and-sweep.
-                                unsafe {
-                                    sweep_ptr.as_ref().color.set(GcColor::White);
-                                }
-                            }
-                            GcColor::Gray => unreachable!("Something was placed in the gray set during sweep phase."),
+                CollectState::Resurrect => {
+                    let (gray, finalize) = (&mut self.gray, &mut self.finalize);
+                    let resurrect = |ptr: NonNull<GcBox<dyn Scan>>| {
+                        let gc_box = unsafe { ptr.as_ref() };
+
+                        // Objects still white after marking are unreachable. If they need to be
+                        // finalized, they are scheduled and marked again so that neither they, nor
+                        // the objects they point to, are swept in this cycle. They'll be deallocated
+                        // by the next cycle, after their finalizer has run.
+                        if gc_box.color.get() == GcColor::White && gc_box.needs_finalize() {
+                            gc_box.color.set(GcColor::Gray);
+                            gray.push(ptr);
+                            finalize.push(ptr);
                         }
+                    };
+
+                    if self.minor {
+                        self.young.iter().copied().for_each(resurrect);
                     } else {
-                        // Done sweeping.
-                        self.sweep_prev = None;
-                        self.state = CollectState::Sleep;
+                        self.arena.for_each(resurrect);
+                    }
+
+                    // Objects reachable from the resurrected objects must be marked.
+                    self.resurrected = true;
+                    self.state = CollectState::Mark;
+                }
+                CollectState::Sweep => {
+                    // SAFETY: We need to be careful in the `Sweep` phase not to take
+                    //         the `GcBox` as a reference/borrow and keep it around.
+                    //         The raw pointer will soon be deallocated turning the
+                    //         would be reference invalid and violating Rust's invariants.
+                    //
+                    //         If all the invariants of the collector hold true, we can safely
+                    //         drop a white `GcBox`. Any pointer remaining in a Gc<T> or Vec<_>
+                    //         is a bug in the collector.
+                    unsafe {
+                        if self.minor {
+                            self.sweep_minor();
+                        } else {
+                            self.sweep_full();
+                        }
                     }
+
+                    // Done sweeping.
+                    self.state = CollectState::Finalize;
+                }
+                CollectState::Finalize => {
+                    // The resurrect phase finds objects in heap order, which follows size classes
+                    // and slot reuse. Finalizers are run in allocation order, oldest first.
+                    // SAFETY: Scheduled objects are resurrected, so they are still allocated.
+                    self.finalize.sort_unstable_by_key(|ptr| unsafe { ptr.as_ref() }.id);
+                    for ptr in self.finalize.drain(..) {
+                        // SAFETY: Resurrected objects survived the sweep, so they
+                        //         and everything they point to are still allocated.
+                        let gc_box = unsafe { ptr.as_ref() };
+                        gc_box.finalized.set(true);
+                        if let Some(finalizer) = gc_box.value.as_finalize() {
+                            finalizer.finalize();
+                        }
+                    }
+
+                    self.state = CollectState::Sleep;
                 }
                 CollectState::Sleep => break,
             }
         }
     }
 
+    /// Deallocates unreachable young objects, and ages the survivors.
+    unsafe fn sweep_minor(&mut self) {
+        let (arena, stats, promote_after) = (&mut self.arena, &mut self.stats, self.config.promote_after);
+        let remembered = self.remembered.get_mut();
+        let mut edges = vec![];
+        let mut condemned = vec![];
+
+        self.young.retain(|sweep_ptr| match sweep_ptr.as_ref().color.get() {
+            GcColor::White => {
+                // println!("Deallocate {:?}", sweep_ptr);
+                debug_assert_eq!(sweep_ptr.as_ref().root.get(), 0, "GcBox deallocated but still rooted.");
+                condemned.push(*sweep_ptr);
+                stats.minor_freed += 1;
+                false
+            }
+            GcColor::Black => {
+                // Reachable from root set.
+                // We change it back to white in preparation for the next mark-and-sweep.
+                sweep_ptr.as_ref().color.set(GcColor::White);
+                let promoted = Self::survive(sweep_ptr.as_ref(), promote_after, stats);
+                if promoted {
+                    Self::remember_young_edges(*sweep_ptr, remembered, &mut edges);
+                }
+                !promoted
+            }
+            GcColor::Gray => unreachable!("Something was placed in the gray set during sweep phase."),
+        });
+        arena.free(&condemned);
+    }
+
+    /// Deallocates all unreachable objects, and ages the surviving young objects.
+    unsafe fn sweep_full(&mut self) {
+        let (stats, promote_after) = (&mut self.stats, self.config.promote_after);
+        let remembered = self.remembered.get_mut();
+        let mut edges = vec![];
+
+        // The young generation is updated before the arena deallocates anything.
+        self.young.retain(|ptr| {
+            let gc_box = ptr.as_ref();
+            if gc_box.color.get() != GcColor::Black {
+                return false;
+            }
+
+            let promoted = Self::survive(gc_box, promote_after, stats);
+            if promoted {
+                Self::remember_young_edges(*ptr, remembered, &mut edges);
+            }
+            !promoted
+        });
+
+        // The remembered set is rebuilt from the old objects that survive, and
+        // still point to young objects after the survivors have been aged.
+        let previous = mem::take(remembered);
+        for ptr in previous {
+            let gc_box = ptr.as_ref();
+            gc_box.remembered.set(false);
+            if gc_box.color.get() == GcColor::Black {
+                Self::remember_young_edges(ptr, remembered, &mut edges);
+            }
+        }
+
+        let stats = &mut self.stats;
+        self.arena.retain(|sweep_ptr| match sweep_ptr.as_ref().color.get() {
+            GcColor::White => {
+                // println!("Deallocate {:?}", sweep_ptr);
+                debug_assert_eq!(sweep_ptr.as_ref().root.get(), 0, "GcBox deallocated but still rooted.");
+                stats.full_freed += 1;
+                false
+            }
+            GcColor::Black => {
+                // println!("Survive {:?}", sweep_ptr);
+                // Reachable from root set.
+                // We change it back to white in preparation for the next mark-and-sweep.
+                sweep_ptr.as_ref().color.set(GcColor::White);
+                true
+            }
+            GcColor::Gray => unreachable!("Something was placed in the gray set during sweep phase."),
+        });
+    }
+
+    /// Ages a young object that survived a collection.
+    ///
+    /// Returns `true` when the object is promoted to the old generation.
+    fn survive(gc_box: &GcBox<dyn Scan>, promote_after: u8, stats: &mut CollectStats) -> bool {
+        let age = gc_box.age.get().saturating_add(1);
+        if age >= promote_after {
+            gc_box.age.set(GcBox::<()>::OLD);
+            stats.promoted += 1;
+            true
+        } else {
+            gc_box.age.set(age);
+            false
+        }
+    }
+
+    /// Adds an old object to the remembered set when it points to young objects.
+    ///
+    /// A minor collection only finds young objects held by old objects through the
+    /// remembered set, so an old object must stay remembered for as long as it may
+    /// point into the young generation.
+    ///
+    /// # Safety
+    ///
+    /// The object, and every object it points to, must still be allocated.
+    unsafe fn remember_young_edges(
+        ptr: NonNull<GcBox<dyn Scan>>,
+        remembered: &mut Vec<NonNull<GcBox<dyn Scan>>>,
+        edges: &mut Vec<NonNull<GcBox<dyn Scan>>>,
+    ) {
+        let gc_box = ptr.as_ref();
+        if gc_box.remembered.get() {
+            return;
+        }
+
+        let mut ctx = Context {
+            gray: edges,
+            minor: false,
+            inspect: true,
+        };
+        gc_box.value.scan(&mut ctx);
+
+        if edges.drain(..).any(|edge| !edge.as_ref().is_old()) {
+            gc_box.remembered.set(true);
+            remembered.push(ptr);
+        }
+    }
+
+    /// Captures the graph of all allocated objects.
+    ///
+    /// Unreachable objects that haven't been collected yet are included.
+    pub fn snapshot(&self) -> HeapSnapshot {
+        let mut edges = vec![];
+        let mut objects = vec![];
+
+        self.arena.for_each(|ptr| {
+            let gc_box = unsafe { ptr.as_ref() };
+
+            let mut ctx = Context {
+                gray: &mut edges,
+                minor: false,
+                inspect: true,
+            };
+            gc_box.value.scan(&mut ctx);
+
+            objects.push(ObjectSnapshot {
+                id: gc_box.id,
+                type_name: gc_box.value.type_name(),
+                size: ::std::mem::size_of_val(gc_box),
+                roots: gc_box.root.get(),
+                edges: edges.drain(..).map(|edge| unsafe { edge.as_ref() }.id).collect(),
+            });
+        });
+
+        objects.sort_by_key(|obj| obj.id);
+        HeapSnapshot { objects }
+    }
+
+    /// Checks the pointers of every marked object after the mark phase.
+    #[cfg(feature = "verify-heap")]
+    fn verify_mark(&self) {
+        let minor = self.minor;
+        self.arena.for_each(|ptr| {
+            let gc_box = unsafe { ptr.as_ref() };
+
+            // During a minor collection old objects are implicitly marked, so
+            // missing write barriers show up as old objects pointing to white objects.
+            let marked = gc_box.color.get() == GcColor::Black || (minor && gc_box.is_old());
+            if marked {
+                verify::verify_marked(ptr, minor);
+            }
+        });
+    }
+
+    /// Lists objects that are part of the root set, but can't be reached from the given values.
+    ///
+    /// Pass the values the VM considers its roots, like the value stack and globals.
+    /// Objects in the result are kept alive by a `Gc<T>` somewhere else, such as a
+    /// handle leaked by the host, or a `Scan` implementation that fails to unroot its
+    /// contents.
+    #[cfg(feature = "verify-heap")]
+    pub fn unreachable_roots(&mut self, roots: &dyn Scan) -> Vec<ObjectInfo> {
+        let mut ctx = Context {
+            gray: &mut self.gray,
+            minor: false,
+            inspect: false,
+        };
+        roots.scan(&mut ctx);
+
+        while let Some(ptr) = self.gray.pop() {
+            let mut ctx = Context {
+                gray: &mut self.gray_new,
+                minor: false,
+                inspect: false,
+            };
+            let gc_box = unsafe { ptr.as_ref() };
+            gc_box.value.scan(&mut ctx);
+            gc_box.color.set(GcColor::Black);
+            self.gray.append(ctx.gray);
+        }
+
+        let mut unreachable = vec![];
+        self.arena.for_each(|ptr| {
+            let gc_box = unsafe { ptr.as_ref() };
+            if gc_box.color.get() == GcColor::White && gc_box.is_root() {
+                unreachable.push(ObjectInfo::new(gc_box));
+            }
+            // Leave the heap as it was before.
+            gc_box.color.set(GcColor::White);
+        });
+        unreachable
+    }
+
+    /// Records a cell whose contents were rooted by a mutable borrow.
+    #[inline]
+    pub(crate) fn mark_dirty(&self, ptr: NonNull<GcBox<dyn Scan>>) {
+        self.dirty.borrow_mut().push(ptr);
+    }
+
+    /// Records a write to the given object.
+    ///
+    /// An old object that is written to may now point to young objects, which
+    /// a minor collection would not find by marking from the young roots alone.
+    #[inline]
+    pub(crate) fn write_barrier(&self, ptr: NonNull<GcBox<dyn Scan>>) {
+        let gc_box = unsafe { ptr.as_ref() };
+        if gc_box.is_old() && !gc_box.remembered.get() {
+            gc_box.remembered.set(true);
+            self.remembered.borrow_mut().push(ptr);
+        }
+    }
+
     #[inline]
     pub(crate) fn scan_ptr(ctx: &mut Context, ptr: NonNull<GcBox<dyn Scan>>) {
         let gc_box = unsafe { ptr.as_ref() };
+
+        #[cfg(feature = "verify-heap")]
+        verify::check_live(gc_box);
+
+        if ctx.inspect {
+            ctx.gray.push(ptr);
+            return;
+        }
+
+        // The old generation is assumed to be reachable during a minor collection.
+        if ctx.minor && gc_box.is_old() {
+            return;
+        }
+
         match gc_box.color.get() {
             GcColor::White => {
                 // We now know that this object can be reached from a gray object
@@ -249,13 +670,14 @@ impl Collector {
 
     #[inline]
     pub(crate) fn unroot_ptr(ptr: NonNull<GcBox<dyn Scan>>) {
-        let gc_box = unsafe { ptr.as_ref() };
-        gc_box.dec();
+        unsafe { ptr.as_ref() }.dec();
     }
 
     fn can_drop(&self) -> bool {
         // TODO: Panic if there are still roots
-        true
+        // A collection cycle may be what panicked, so another cycle could panic
+        // again and abort.
+        !::std::thread::panicking()
     }
 }
 
@@ -265,7 +687,15 @@ impl Drop for Collector {
             // println!("Collector Drop");
             // Deallocate owned pointers.
             self.collect();
+            // Objects with finalizers survive the cycle in which they are
+            // finalized, so a second cycle is needed to deallocate them.
+            self.collect();
             assert_eq!(self.len(), 0, "Collector dropped but some items are still reachable");
+        } else {
+            // The panic may have left the heap inconsistent, and `Gc<T>` pointers into it
+            // may still be dropped while unwinding. Freeing the objects could touch freed
+            // memory, so their pages are leaked instead.
+            mem::forget(mem::replace(&mut self.arena, Arena::new()));
         }
     }
 }
@@ -280,6 +710,8 @@ impl Default for Collector {
 enum CollectState {
     Wake,
     Mark,
+    Resurrect,
     Sweep,
+    Finalize,
     Sleep,
 }
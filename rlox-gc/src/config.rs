@@ -0,0 +1,29 @@
+//! Collector configuration.
+
+/// Tuning parameters for a [`Collector`](struct.Collector.html).
+#[derive(Debug, Clone)]
+pub struct CollectorConfig {
+    /// Number of collection cycles an object must survive in the young
+    /// generation before it's promoted to the old generation.
+    ///
+    /// Old objects are only collected by a full collection.
+    pub promote_after: u8,
+    /// Upper limit on the size in bytes of the memory reserved for objects.
+    ///
+    /// Small objects are packed into pages, and the whole page counts against the
+    /// limit when it's reserved. Memory owned by the objects themselves, like the
+    /// buffer of a `Vec<T>`, is not counted.
+    ///
+    /// An allocation that would exceed the limit first runs a full collection,
+    /// and fails if not enough memory was freed. `None` means no limit.
+    pub max_heap_bytes: Option<usize>,
+}
+
+impl Default for CollectorConfig {
+    fn default() -> Self {
+        CollectorConfig {
+            promote_after: 2,
+            max_heap_bytes: None,
+        }
+    }
+}
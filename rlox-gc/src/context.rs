@@ -5,4 +5,9 @@ use std::ptr::NonNull;
 pub struct Context<'ctx> {
     /// Queue of objects that have been marked gray during a scan.
     pub(crate) gray: &'ctx mut Vec<NonNull<GcBox<dyn Scan>>>,
+    /// During a minor collection only the young generation is marked.
+    pub(crate) minor: bool,
+    /// Pointers are only collected into the queue, without being marked.
+    /// Used to inspect the edges of the object graph.
+    pub(crate) inspect: bool,
 }
@@ -0,0 +1,26 @@
+//! Collector errors.
+use std::{error::Error, fmt};
+
+/// Allocation failed because the heap would grow past
+/// [`CollectorConfig::max_heap_bytes`](struct.CollectorConfig.html#structfield.max_heap_bytes).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllocError {
+    /// Size in bytes of the memory that would have been reserved for the object.
+    pub size: usize,
+    /// Size in bytes of the memory reserved by the heap after collection.
+    pub heap_bytes: usize,
+    /// Configured heap limit in bytes.
+    pub limit: usize,
+}
+
+impl Error for AllocError {}
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "out of memory: allocating {} bytes with {} of {} bytes in use",
+            self.size, self.heap_bytes, self.limit
+        )
+    }
+}
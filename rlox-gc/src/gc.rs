@@ -8,7 +8,7 @@ use std::{
 };
 
 pub struct Gc<T: Scan + ?Sized> {
-    ptr: NonNull<GcBox<T>>,
+    pub(crate) ptr: NonNull<GcBox<T>>,
 }
 
 impl<T: Scan + ?Sized> Gc<T> {
@@ -36,8 +36,6 @@ impl<T: Scan + ?Sized> Gc<T> {
     }
 }
 
-impl<T: Scan + Sized> Gc<T> {}
-
 impl<T: Debug + Scan + ?Sized> Debug for Gc<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Gc")
@@ -79,10 +77,48 @@ unsafe impl<T: 'static + Scan> Scan for Gc<T> {
     }
 
     fn root(&self) {
-        todo!("Re-root the Gc when it leaves a `GcCell`")
+        #[cfg(feature = "verify-heap")]
+        crate::verify::record(self.ptr);
+
+        self.inner().incr();
     }
 
     fn unroot(&self) {
+        #[cfg(feature = "verify-heap")]
+        crate::verify::record(self.ptr);
+
+        Collector::unroot_ptr(self.ptr);
+    }
+}
+
+impl<T: 'static + Scan> Gc<T> {
+    /// Converts the pointer into a `Gc<dyn Scan>`, erasing the type of the value.
+    ///
+    /// Erased pointers can be stored side by side, for example in a `Vec<Gc<dyn Scan>>`.
+    pub fn into_dyn(gc: Gc<T>) -> Gc<dyn Scan> {
+        let ptr: NonNull<GcBox<dyn Scan>> = gc.ptr;
+        // The root count held by `gc` is handed over to the new pointer.
+        std::mem::forget(gc);
+        Gc::from_inner(ptr)
+    }
+}
+
+unsafe impl Scan for Gc<dyn Scan> {
+    fn scan(&self, ctx: &mut Context) {
+        Collector::scan_ptr(ctx, self.ptr);
+    }
+
+    fn root(&self) {
+        #[cfg(feature = "verify-heap")]
+        crate::verify::record(self.ptr);
+
+        self.inner().incr();
+    }
+
+    fn unroot(&self) {
+        #[cfg(feature = "verify-heap")]
+        crate::verify::record(self.ptr);
+
         Collector::unroot_ptr(self.ptr);
     }
 }
@@ -91,14 +127,31 @@ unsafe impl<T: 'static + Scan> Scan for Gc<T> {
 #[derive(Debug)]
 #[doc(hidden)]
 pub(crate) struct GcBox<T: Scan + ?Sized> {
+    /// Allocation number, unique within the collector. Identifies the object in heap snapshots,
+    /// where addresses would be reused by later allocations.
+    pub(crate) id: u64,
+    /// Id of the collector that allocated the box.
+    pub(crate) heap: u32,
     pub(crate) root: Cell<u32>,
     pub(crate) color: Cell<GcColor>,
-    pub(crate) next: Cell<Option<NonNull<GcBox<dyn Scan>>>>,
+    /// Set once the value's finalizer has run, so it is never run twice.
+    pub(crate) finalized: Cell<bool>,
+    /// Number of collection cycles survived while in the young generation.
+    /// Set to [`GcBox::OLD`] once the box is promoted.
+    pub(crate) age: Cell<u8>,
+    /// Set while an old box is in the collector's remembered set.
+    pub(crate) remembered: Cell<bool>,
     pub(crate) value: T,
 }
 
 impl<T: Scan + ?Sized> GcBox<T> {
+    /// Age of a box that has been promoted to the old generation.
+    pub(crate) const OLD: u8 = u8::MAX;
+
     pub(crate) fn dec(&self) {
+        #[cfg(feature = "verify-heap")]
+        crate::verify::check_live(self);
+
         // Unlike an `Rc` we can decrement the reference count even though
         // it's already 0. Decrement can happen when an owning `Gc<T>` is
         // dropped, and when it is moved into another `Gc<T>` via `Collector::alloc_gc`.
@@ -119,6 +172,17 @@ impl<T: Scan + ?Sized> GcBox<T> {
     pub(crate) fn is_root(&self) -> bool {
         self.root.get() > 0
     }
+
+    #[inline(always)]
+    pub(crate) fn is_old(&self) -> bool {
+        self.age.get() == Self::OLD
+    }
+
+    /// Indicates that the value has a finalizer that has not run yet.
+    #[inline(always)]
+    pub(crate) fn needs_finalize(&self) -> bool {
+        !self.finalized.get() && self.value.as_finalize().is_some()
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -166,9 +230,13 @@ mod test {
     fn test_gcbox_color_pack() {
         for color in &[GcColor::White, GcColor::Gray, GcColor::Black] {
             let gcbox = GcBox {
+                id: 1,
+                heap: 1,
                 root: Cell::new(1),
                 color: Cell::new(*color),
-                next: Cell::new(None),
+                finalized: Cell::new(false),
+                age: Cell::new(0),
+                remembered: Cell::new(false),
                 value: (),
             };
 
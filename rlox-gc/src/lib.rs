@@ -1,13 +1,23 @@
 mod arena;
 mod cell;
 mod collect;
+mod config;
 pub mod context;
+mod error;
 mod gc;
 pub mod scan;
 mod scan_impl;
+pub mod snapshot;
+mod stats;
+#[cfg(feature = "verify-heap")]
+pub mod verify;
 
+pub use cell::{GcCell, GcCellRefMut};
 pub use collect::Collector;
+pub use config::CollectorConfig;
+pub use error::AllocError;
 pub use gc::Gc;
+pub use stats::CollectStats;
 
 #[cfg(feature = "derive")]
 pub mod derive {
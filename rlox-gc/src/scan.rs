@@ -1,6 +1,11 @@
 //! Trait for a value that can live in the garbage collector.
 use crate::context::Context;
 
+/// # Safety
+///
+/// Implementations must visit every `Gc<T>` pointer contained in the value, in each of `scan`,
+/// `root` and `unroot`. A pointer that is not visited during `scan` will be collected while it is
+/// still reachable, leaving a dangling pointer behind.
 pub unsafe trait Scan {
     fn scan(&self, ctx: &mut Context<'_>);
 
@@ -14,4 +19,40 @@ pub unsafe trait Scan {
     /// Unsafe because this will allow them to be garbage collected, even if you hold on
     /// to this value.
     fn unroot(&self);
+
+    /// Name of the value's type, used to describe objects when inspecting the heap.
+    #[inline(always)]
+    fn type_name(&self) -> &'static str {
+        ::std::any::type_name::<Self>()
+    }
+
+    /// Returns the finalizer of this value, if it has one.
+    ///
+    /// Values that return `Some` will have [`Finalize::finalize`] called once
+    /// after they become unreachable, before they are deallocated.
+    ///
+    /// The default implementation opts out of finalization.
+    #[inline(always)]
+    fn as_finalize(&self) -> Option<&dyn Finalize> {
+        None
+    }
+}
+
+/// Hook that runs when a value in the garbage collector becomes unreachable.
+///
+/// Finalizers are opt-in. A type must implement [`Scan::as_finalize`] to return
+/// itself, or use `#[scan(finalize)]` when deriving `Scan`.
+///
+/// Unlike `Drop`, finalizers run while the whole object graph is still intact.
+/// It is safe to access other `Gc<T>` pointers held by the value, because
+/// unreachable objects are only deallocated in a later collection cycle, after
+/// all their finalizers have run. A finalized object can observe other
+/// objects that have already been finalized, but never objects that have been freed.
+///
+/// Finalizers are run exactly once per object.
+///
+/// Objects that become unreachable in the same cycle are finalized in allocation
+/// order, oldest first.
+pub trait Finalize {
+    fn finalize(&self);
 }
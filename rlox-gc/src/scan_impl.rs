@@ -1,6 +1,9 @@
 //! Scan implementations for `std` types.
 use crate::{context::Context, scan::Scan};
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    rc::Rc,
+};
 
 macro_rules! static_scan {
     ($v:ty) => {
@@ -30,70 +33,36 @@ static_scan!(i32);
 static_scan!(i64);
 static_scan!(i128);
 static_scan!(f32);
+static_scan!(usize);
+static_scan!(isize);
 static_scan!(f64);
+static_scan!(char);
 static_scan!(String);
+static_scan!(&'static str);
 
-macro_rules! array_scan {
-    ($n:literal) => {
-        unsafe impl<T: Scan> Scan for [T; $n] {
-            #[inline(always)]
-            fn scan(&self, ctx: &mut Context) {
-                for elem in self {
-                    elem.scan(ctx)
-                }
-            }
+unsafe impl<T: Scan, const N: usize> Scan for [T; N] {
+    #[inline(always)]
+    fn scan(&self, ctx: &mut Context) {
+        for elem in self {
+            elem.scan(ctx)
+        }
+    }
 
-            #[inline(always)]
-            fn root(&self) {
-                for elem in self {
-                    elem.root()
-                }
-            }
+    #[inline(always)]
+    fn root(&self) {
+        for elem in self {
+            elem.root()
+        }
+    }
 
-            #[inline(always)]
-            fn unroot(&self) {
-                for elem in self {
-                    elem.unroot()
-                }
-            }
+    #[inline(always)]
+    fn unroot(&self) {
+        for elem in self {
+            elem.unroot()
         }
-    };
+    }
 }
 
-array_scan!(0);
-array_scan!(1);
-array_scan!(2);
-array_scan!(3);
-array_scan!(4);
-array_scan!(5);
-array_scan!(6);
-array_scan!(7);
-array_scan!(8);
-array_scan!(9);
-array_scan!(10);
-array_scan!(11);
-array_scan!(12);
-array_scan!(13);
-array_scan!(14);
-array_scan!(15);
-array_scan!(16);
-array_scan!(17);
-array_scan!(18);
-array_scan!(19);
-array_scan!(20);
-array_scan!(21);
-array_scan!(22);
-array_scan!(23);
-array_scan!(24);
-array_scan!(25);
-array_scan!(26);
-array_scan!(27);
-array_scan!(28);
-array_scan!(29);
-array_scan!(30);
-array_scan!(31);
-array_scan!(32);
-
 unsafe impl<T: Scan> Scan for Option<T> {
     #[inline]
     fn scan(&self, ctx: &mut Context<'_>) {
@@ -120,9 +89,7 @@ unsafe impl<T: Scan> Scan for Option<T> {
 unsafe impl<T: Scan> Scan for Vec<T> {
     #[inline]
     fn scan(&self, ctx: &mut Context<'_>) {
-        println!("Scan Vec<T>");
         for item in self {
-            println!("  Scan Vec<T> item");
             item.scan(ctx);
         }
     }
@@ -149,7 +116,6 @@ where
 {
     #[inline]
     fn scan(&self, ctx: &mut Context<'_>) {
-        println!("Scan HashMap<K, V>");
         for (k, v) in self {
             k.scan(ctx);
             v.scan(ctx);
@@ -172,3 +138,170 @@ where
         }
     }
 }
+
+/// Scan for collections that iterate over their elements by reference.
+macro_rules! iter_scan {
+    ($v:ty) => {
+        unsafe impl<T: Scan> Scan for $v {
+            #[inline]
+            fn scan(&self, ctx: &mut Context<'_>) {
+                for item in self.iter() {
+                    item.scan(ctx);
+                }
+            }
+
+            #[inline]
+            fn root(&self) {
+                for item in self.iter() {
+                    item.root();
+                }
+            }
+
+            #[inline]
+            fn unroot(&self) {
+                for item in self.iter() {
+                    item.unroot();
+                }
+            }
+        }
+    };
+}
+
+iter_scan!([T]);
+iter_scan!(VecDeque<T>);
+iter_scan!(BTreeSet<T>);
+iter_scan!(HashSet<T>);
+
+unsafe impl<K, V> Scan for BTreeMap<K, V>
+where
+    K: Scan,
+    V: Scan,
+{
+    #[inline]
+    fn scan(&self, ctx: &mut Context<'_>) {
+        for (k, v) in self {
+            k.scan(ctx);
+            v.scan(ctx);
+        }
+    }
+
+    #[inline]
+    fn root(&self) {
+        for (k, v) in self {
+            k.root();
+            v.root();
+        }
+    }
+
+    #[inline]
+    fn unroot(&self) {
+        for (k, v) in self {
+            k.unroot();
+            v.unroot();
+        }
+    }
+}
+
+unsafe impl<T: Scan + ?Sized> Scan for Box<T> {
+    #[inline]
+    fn scan(&self, ctx: &mut Context<'_>) {
+        (**self).scan(ctx);
+    }
+
+    #[inline]
+    fn root(&self) {
+        (**self).root();
+    }
+
+    #[inline]
+    fn unroot(&self) {
+        (**self).unroot();
+    }
+}
+
+unsafe impl<T: Scan, E: Scan> Scan for Result<T, E> {
+    #[inline]
+    fn scan(&self, ctx: &mut Context<'_>) {
+        match self {
+            Ok(val) => val.scan(ctx),
+            Err(err) => err.scan(ctx),
+        }
+    }
+
+    #[inline]
+    fn root(&self) {
+        match self {
+            Ok(val) => val.root(),
+            Err(err) => err.root(),
+        }
+    }
+
+    #[inline]
+    fn unroot(&self) {
+        match self {
+            Ok(val) => val.unroot(),
+            Err(err) => err.unroot(),
+        }
+    }
+}
+
+/// Scan-only implementation.
+///
+/// The contents of an `Rc` may be shared with owners outside of the collector,
+/// so moving an `Rc` into the collector can't unroot them. Pointers inside an
+/// `Rc` are scanned, but stay part of the root set for as long as the `Rc`
+/// is alive. Cycles through an `Rc` are never collected.
+unsafe impl<T: Scan + ?Sized> Scan for Rc<T> {
+    #[inline]
+    fn scan(&self, ctx: &mut Context<'_>) {
+        (**self).scan(ctx);
+    }
+
+    #[inline(always)]
+    fn root(&self) {
+        // The contents are never unrooted, so there is nothing to root. The heap
+        // verifier is told about them, since `scan` visits them.
+        #[cfg(feature = "verify-heap")]
+        crate::verify::record_scan_only(&**self);
+    }
+
+    #[inline(always)]
+    fn unroot(&self) {
+        #[cfg(feature = "verify-heap")]
+        crate::verify::record_scan_only(&**self);
+    }
+}
+
+macro_rules! tuple_scan {
+    ($($t:ident $i:tt),+) => {
+        unsafe impl<$($t: Scan),+> Scan for ($($t,)+) {
+            #[inline]
+            fn scan(&self, ctx: &mut Context<'_>) {
+                $(self.$i.scan(ctx);)+
+            }
+
+            #[inline]
+            fn root(&self) {
+                $(self.$i.root();)+
+            }
+
+            #[inline]
+            fn unroot(&self) {
+                $(self.$i.unroot();)+
+            }
+        }
+    };
+}
+
+tuple_scan!(A 0);
+tuple_scan!(A 0, B 1);
+tuple_scan!(A 0, B 1, C 2);
+tuple_scan!(A 0, B 1, C 2, D 3);
+tuple_scan!(A 0, B 1, C 2, D 3, E 4);
+tuple_scan!(A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_scan!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_scan!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+tuple_scan!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8);
+tuple_scan!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9);
+tuple_scan!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10);
+tuple_scan!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11);
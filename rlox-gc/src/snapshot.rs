@@ -0,0 +1,253 @@
+//! Snapshots of the object graph, for finding out what is holding on to memory.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Write as FmtWrite},
+};
+
+/// Graph of the objects alive in a collector at a point in time.
+///
+/// Objects are ordered by id, which is allocation order, so the same program
+/// gives the same output on every run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeapSnapshot {
+    pub objects: Vec<ObjectSnapshot>,
+}
+
+/// Object in a [`HeapSnapshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectSnapshot {
+    /// Allocation number of the object. Ids increase with every allocation, and
+    /// are never reused by the same collector.
+    pub id: u64,
+    /// Rust type name of the value.
+    pub type_name: &'static str,
+    /// Size in bytes of the object and its header.
+    pub size: usize,
+    /// Number of `Gc<T>` pointers to the object outside of the heap.
+    pub roots: u32,
+    /// Ids of the objects this object points to, in the order `Scan` visited them.
+    pub edges: Vec<u64>,
+}
+
+impl HeapSnapshot {
+    /// Total size in bytes of all objects in the snapshot.
+    pub fn total_size(&self) -> usize {
+        self.objects.iter().map(|obj| obj.size).sum()
+    }
+
+    /// Looks up an object by id.
+    pub fn get(&self, id: u64) -> Option<&ObjectSnapshot> {
+        self.objects
+            .binary_search_by_key(&id, |obj| obj.id)
+            .ok()
+            .map(|index| &self.objects[index])
+    }
+
+    /// Compares this snapshot, taken earlier, with a later one.
+    ///
+    /// An object is considered the same in both snapshots when it has the same id.
+    /// Both snapshots must be taken from the same collector.
+    pub fn diff<'a>(&'a self, later: &'a HeapSnapshot) -> SnapshotDiff<'a> {
+        SnapshotDiff {
+            added: later.objects.iter().filter(|obj| self.get(obj.id).is_none()).collect(),
+            removed: self.objects.iter().filter(|obj| later.get(obj.id).is_none()).collect(),
+        }
+    }
+
+    /// Serializes the snapshot as JSON.
+    ///
+    /// ```json
+    /// {"objects":[{"id":1,"type":"u32","size":16,"roots":1,"edges":[]}]}
+    /// ```
+    pub fn to_json(&self) -> String {
+        let mut s = String::new();
+        self.write_json(&mut s).expect("writing to string");
+        s
+    }
+
+    pub fn write_json<W>(&self, w: &mut W) -> fmt::Result
+    where
+        W: FmtWrite,
+    {
+        write!(w, "{{\"objects\":[")?;
+        for (index, obj) in self.objects.iter().enumerate() {
+            if index > 0 {
+                write!(w, ",")?;
+            }
+            write!(w, "{{\"id\":{},\"type\":", obj.id)?;
+            write_json_string(w, obj.type_name)?;
+            write!(w, ",\"size\":{},\"roots\":{},\"edges\":[", obj.size, obj.roots)?;
+            for (index, edge) in obj.edges.iter().enumerate() {
+                if index > 0 {
+                    write!(w, ",")?;
+                }
+                write!(w, "{}", edge)?;
+            }
+            write!(w, "]}}")?;
+        }
+        write!(w, "]}}")
+    }
+
+    /// Serializes the snapshot as a Graphviz DOT graph.
+    ///
+    /// Objects that are part of the root set are drawn with a bold outline.
+    pub fn to_dot(&self) -> String {
+        let mut s = String::new();
+        self.write_dot(&mut s).expect("writing to string");
+        s
+    }
+
+    pub fn write_dot<W>(&self, w: &mut W) -> fmt::Result
+    where
+        W: FmtWrite,
+    {
+        writeln!(w, "digraph heap {{")?;
+        for obj in &self.objects {
+            write!(w, "    n{} [label=", obj.id)?;
+            write_json_string(w, &format!("{}\n{} bytes", obj.type_name, obj.size))?;
+            if obj.roots > 0 {
+                write!(w, ", style=bold")?;
+            }
+            writeln!(w, "];")?;
+        }
+        for obj in &self.objects {
+            for edge in &obj.edges {
+                writeln!(w, "    n{} -> n{};", obj.id, edge)?;
+            }
+        }
+        writeln!(w, "}}")
+    }
+}
+
+/// Objects that differ between two snapshots.
+#[derive(Debug)]
+pub struct SnapshotDiff<'a> {
+    /// Objects in the later snapshot that were not in the earlier one.
+    pub added: Vec<&'a ObjectSnapshot>,
+    /// Objects in the earlier snapshot that are not in the later one.
+    pub removed: Vec<&'a ObjectSnapshot>,
+}
+
+impl<'a> SnapshotDiff<'a> {
+    /// Change in object count and bytes, per type.
+    ///
+    /// Types without a change are left out.
+    pub fn by_type(&self) -> BTreeMap<&'static str, TypeDelta> {
+        let mut deltas = BTreeMap::<&'static str, TypeDelta>::new();
+
+        for obj in &self.added {
+            let delta = deltas.entry(obj.type_name).or_default();
+            delta.count += 1;
+            delta.bytes += obj.size as isize;
+        }
+        for obj in &self.removed {
+            let delta = deltas.entry(obj.type_name).or_default();
+            delta.count -= 1;
+            delta.bytes -= obj.size as isize;
+        }
+
+        deltas.retain(|_, delta| delta.count != 0 || delta.bytes != 0);
+        deltas
+    }
+}
+
+impl<'a> fmt::Display for SnapshotDiff<'a> {
+    /// Writes a table of the change per type, with the largest growth first.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut deltas = self.by_type().into_iter().collect::<Vec<_>>();
+        deltas.sort_by_key(|(name, delta)| (-delta.bytes, *name));
+
+        writeln!(f, "{:>10} {:>12}  type", "count", "bytes")?;
+        for (name, delta) in deltas {
+            writeln!(f, "{:>+10} {:>+12}  {}", delta.count, delta.bytes, name)?;
+        }
+
+        let types = self
+            .added
+            .iter()
+            .chain(self.removed.iter())
+            .map(|obj| obj.type_name)
+            .collect::<BTreeSet<_>>();
+        write!(
+            f,
+            "{} added, {} removed, across {} types",
+            self.added.len(),
+            self.removed.len(),
+            types.len()
+        )
+    }
+}
+
+/// Change in the number of objects of a type between two snapshots.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TypeDelta {
+    pub count: isize,
+    pub bytes: isize,
+}
+
+/// Writes a quoted string with JSON escapes, which DOT also accepts.
+fn write_json_string<W>(w: &mut W, s: &str) -> fmt::Result
+where
+    W: FmtWrite,
+{
+    write!(w, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(w, "\\\"")?,
+            '\\' => write!(w, "\\\\")?,
+            '\n' => write!(w, "\\n")?,
+            c if c.is_control() => write!(w, "\\u{:04x}", c as u32)?,
+            c => write!(w, "{}", c)?,
+        }
+    }
+    write!(w, "\"")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn object(id: u64, type_name: &'static str, edges: Vec<u64>) -> ObjectSnapshot {
+        ObjectSnapshot {
+            id,
+            type_name,
+            size: 16,
+            roots: 0,
+            edges,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_json() {
+        let snapshot = HeapSnapshot {
+            objects: vec![object(1, "Vec<\"a\">", vec![2]), object(2, "u32", vec![])],
+        };
+
+        assert_eq!(
+            snapshot.to_json(),
+            r#"{"objects":[{"id":1,"type":"Vec<\"a\">","size":16,"roots":0,"edges":[2]},{"id":2,"type":"u32","size":16,"roots":0,"edges":[]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_snapshot_diff() {
+        let before = HeapSnapshot {
+            objects: vec![object(1, "u32", vec![]), object(2, "u32", vec![])],
+        };
+        let after = HeapSnapshot {
+            objects: vec![
+                object(1, "u32", vec![]),
+                object(3, "String", vec![]),
+                object(4, "String", vec![]),
+            ],
+        };
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.added.len(), 2);
+        assert_eq!(diff.removed.len(), 1);
+
+        let by_type = diff.by_type();
+        assert_eq!(by_type["String"], TypeDelta { count: 2, bytes: 32 });
+        assert_eq!(by_type["u32"], TypeDelta { count: -1, bytes: -16 });
+    }
+}
@@ -0,0 +1,16 @@
+//! Collector statistics.
+
+/// Counters kept by a [`Collector`](struct.Collector.html) across its lifetime.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CollectStats {
+    /// Number of collections of the young generation only.
+    pub minor_collections: usize,
+    /// Number of collections of the whole heap.
+    pub full_collections: usize,
+    /// Number of objects deallocated by minor collections.
+    pub minor_freed: usize,
+    /// Number of objects deallocated by full collections.
+    pub full_freed: usize,
+    /// Number of objects promoted from the young generation to the old generation.
+    pub promoted: usize,
+}
@@ -0,0 +1,166 @@
+//! Heap verification for catching `Scan` implementation bugs.
+//!
+//! Enabled with the `verify-heap` feature. After each mark phase, the collector
+//! checks every marked object:
+//!
+//! - The pointers visited by `Scan::scan` must be the same as the pointers
+//!   visited by `Scan::root` and by `Scan::unroot`, which catches fields
+//!   forgotten in any of them. Scan-only implementations, like the one for
+//!   `Rc`, report their pointers to the verifier from `Scan::root` and
+//!   `Scan::unroot` instead.
+//! - None of the pointers may still be white, which would mean a reachable
+//!   object is about to be swept.
+//!
+//! Freed memory is poisoned, so a dangling pointer that is scanned or dropped
+//! panics instead of silently reading reused memory.
+use crate::{
+    context::Context,
+    gc::{GcBox, GcColor},
+    scan::Scan,
+};
+use std::{cell::RefCell, ptr::NonNull};
+
+/// Byte written over the memory of deallocated objects.
+pub(crate) const POISON: u8 = 0xDE;
+
+/// Pointers visited by `Scan::root` or `Scan::unroot`.
+type Recording = Option<Vec<NonNull<GcBox<dyn Scan>>>>;
+
+thread_local! {
+    /// Pointers visited by `Scan::root` or `Scan::unroot` while an object is being verified.
+    static VISITED: RefCell<Recording> = RefCell::new(None);
+}
+
+/// Information about an object in the heap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectInfo {
+    /// Address of the object, which identifies it for as long as it's alive.
+    pub addr: usize,
+    /// Rust type name of the value.
+    pub type_name: &'static str,
+    /// Number of `Gc<T>` pointers to the object outside of the heap.
+    pub roots: u32,
+    /// Size in bytes of the object and its header.
+    pub size: usize,
+}
+
+impl ObjectInfo {
+    pub(crate) fn new(gc_box: &GcBox<dyn Scan>) -> Self {
+        ObjectInfo {
+            addr: gc_box as *const GcBox<dyn Scan> as *const u8 as usize,
+            type_name: gc_box.value.type_name(),
+            roots: gc_box.root.get(),
+            size: ::std::mem::size_of_val(gc_box),
+        }
+    }
+}
+
+pub(crate) unsafe fn poison(ptr: *mut u8, size: usize) {
+    ptr.write_bytes(POISON, size);
+}
+
+/// Panics if the given box has been deallocated and poisoned.
+#[inline]
+pub(crate) fn check_live<T: Scan + ?Sized>(gc_box: &GcBox<T>) {
+    let poisoned = u32::from_ne_bytes([POISON; 4]);
+    assert_ne!(
+        gc_box.root.get(),
+        poisoned,
+        "Use of a deallocated GcBox {:p}",
+        gc_box as *const GcBox<T>
+    );
+}
+
+/// Records the pointer when called from `Scan::root` or `Scan::unroot` during verification.
+#[inline]
+pub(crate) fn record(ptr: NonNull<GcBox<dyn Scan>>) {
+    VISITED.with(|visited| {
+        if let Some(visited) = visited.borrow_mut().as_mut() {
+            visited.push(ptr);
+        }
+    });
+}
+
+/// Returns the pointers recorded while running the given function.
+fn record_visits(f: impl FnOnce()) -> Vec<NonNull<GcBox<dyn Scan>>> {
+    VISITED.with(|visited| *visited.borrow_mut() = Some(vec![]));
+    f();
+    VISITED.with(|visited| visited.borrow_mut().take()).unwrap_or_default()
+}
+
+/// Records the pointers held by a value whose `Scan` implementation only scans them,
+/// and leaves them rooted, as the `Rc` implementation does.
+///
+/// Called from `Scan::root` and `Scan::unroot` instead of rooting or unrooting the
+/// contents. The pointers count as visited for the comparison with `Scan::scan`,
+/// without changing any root counts.
+pub(crate) fn record_scan_only<T: Scan + ?Sized>(value: &T) {
+    if VISITED.with(|visited| visited.borrow().is_none()) {
+        return;
+    }
+
+    let mut scanned = vec![];
+    value.scan(&mut Context {
+        gray: &mut scanned,
+        minor: false,
+        inspect: true,
+    });
+    VISITED.with(|visited| {
+        if let Some(visited) = visited.borrow_mut().as_mut() {
+            visited.append(&mut scanned);
+        }
+    });
+}
+
+/// Checks the pointers held by a marked object.
+///
+/// During a minor collection pointers to the old generation are ignored.
+pub(crate) fn verify_marked(ptr: NonNull<GcBox<dyn Scan>>, minor: bool) {
+    let gc_box = unsafe { ptr.as_ref() };
+
+    let mut scanned = vec![];
+    gc_box.value.scan(&mut Context {
+        gray: &mut scanned,
+        minor,
+        inspect: true,
+    });
+
+    // Rooting and unrooting leaves the root counts as they were.
+    let rooted = record_visits(|| gc_box.value.root());
+    let unrooted = record_visits(|| gc_box.value.unroot());
+
+    let addrs = |ptrs: &[NonNull<GcBox<dyn Scan>>]| {
+        let mut addrs = ptrs.iter().map(|ptr| ptr.as_ptr() as *const u8).collect::<Vec<_>>();
+        addrs.sort_unstable();
+        addrs
+    };
+    assert_eq!(
+        addrs(&scanned),
+        addrs(&rooted),
+        "Scan implementation of {:p} visits different pointers in `scan` and `root`",
+        gc_box
+    );
+    assert_eq!(
+        addrs(&scanned),
+        addrs(&unrooted),
+        "Scan implementation of {:p} visits different pointers in `scan` and `unroot`",
+        gc_box
+    );
+
+    for child_ptr in scanned.into_iter().chain(rooted).chain(unrooted) {
+        let child = unsafe { child_ptr.as_ref() };
+        check_live(child);
+
+        if minor && child.is_old() {
+            continue;
+        }
+
+        assert_ne!(
+            child.color.get(),
+            GcColor::White,
+            "Marked object {:p} points to unmarked object {:p}",
+            gc_box,
+            child
+        );
+    }
+}
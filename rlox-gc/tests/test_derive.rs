@@ -1,6 +1,8 @@
 #![cfg(feature = "derive")]
+#![allow(dead_code)]
 
-use rlox_gc::derive::Scan;
+use rlox_gc::{derive::Scan, scan::Finalize, Collector, Gc, GcCell};
+use std::{cell::Cell, fs::File, marker::PhantomData};
 
 #[test]
 fn test_basic_derive() {
@@ -22,3 +24,207 @@ fn test_basic_derive() {
     #[derive(Scan)]
     struct Baz;
 }
+
+#[test]
+fn test_derive_finalize() {
+    thread_local! {
+        static FINALIZED: Cell<u32> = const { Cell::new(0) };
+    }
+
+    #[derive(Scan)]
+    #[scan(finalize)]
+    struct Handle {
+        fd: u32,
+    }
+
+    impl Finalize for Handle {
+        fn finalize(&self) {
+            FINALIZED.with(|count| count.set(count.get() + self.fd));
+        }
+    }
+
+    let mut gc = Collector::new();
+    drop(gc.alloc(Handle { fd: 3 }));
+    gc.collect();
+    assert_eq!(FINALIZED.with(Cell::get), 3);
+}
+
+#[derive(Scan)]
+enum Never {}
+
+#[test]
+fn test_derive_empty_enum() {
+    let mut gc = Collector::new();
+    let empty = gc.alloc(Vec::<Never>::new());
+    gc.collect();
+    assert_eq!(gc.len(), 1);
+    drop(empty);
+}
+
+#[derive(Scan)]
+enum Obj {
+    Nil,
+    Number(f64),
+    String(String),
+    Closure { arity: u8, upvalues: Vec<Gc<Obj>> },
+    Instance(Gc<GcCell<Vec<Gc<Obj>>>>),
+}
+
+#[test]
+fn test_derive_enum() {
+    let mut gc = Collector::new();
+
+    let name = gc.alloc(Obj::String("counter".to_string()));
+    let number = gc.alloc(Obj::Number(1.0));
+    let closure = gc.alloc(Obj::Closure {
+        arity: 0,
+        upvalues: vec![name, number],
+    });
+    let nil = gc.alloc(Obj::Nil);
+    let fields = gc.alloc(GcCell::new(vec![closure, nil]));
+    let instance = gc.alloc(Obj::Instance(fields));
+    assert_eq!(gc.len(), 6);
+
+    // Everything is reachable through the instance.
+    gc.collect();
+    assert_eq!(gc.len(), 6);
+
+    if let Obj::Instance(fields) = &*instance {
+        match &*fields.borrow()[0] {
+            Obj::Closure { upvalues, .. } => assert!(matches!(&*upvalues[0], Obj::String(s) if s == "counter")),
+            _ => panic!("expected closure"),
+        }
+    }
+
+    drop(instance);
+    gc.collect();
+    assert!(gc.is_empty());
+}
+
+#[test]
+fn test_derive_generics() {
+    #[derive(Scan)]
+    struct Pair<A, B> {
+        first: A,
+        second: B,
+    }
+
+    /// `T` is only used by a skipped field, so it needs no `Scan` bound.
+    #[derive(Scan)]
+    struct Tagged<'a, T> {
+        value: Gc<u32>,
+        #[scan(skip)]
+        tag: &'a str,
+        #[scan(skip)]
+        marker: PhantomData<T>,
+    }
+
+    #[derive(Scan)]
+    enum Either<L, R>
+    where
+        L: Clone,
+    {
+        Left(L),
+        Right(R),
+    }
+
+    /// `Gc<T>` needs `T: 'static`, which a bound on `T` alone wouldn't give.
+    #[derive(Scan)]
+    struct Boxed<T: rlox_gc::scan::Scan> {
+        value: Gc<T>,
+    }
+
+    /// Fields that point back to the type itself get no bound.
+    #[derive(Scan)]
+    struct Node<T: rlox_gc::scan::Scan + 'static> {
+        value: Gc<T>,
+        children: Vec<Gc<Node<T>>>,
+    }
+
+    struct NotScan;
+
+    let mut gc = Collector::new();
+    let value = gc.alloc(1u32);
+    let pair = gc.alloc(Pair {
+        first: value.clone(),
+        second: Either::<Gc<u32>, u8>::Left(value),
+    });
+    let tagged = Tagged::<'static, NotScan> {
+        value: gc.alloc(2u32),
+        tag: "tag",
+        marker: PhantomData,
+    };
+    let tagged = gc.alloc(tagged);
+    let leaf = Node {
+        value: gc.alloc(3u32),
+        children: vec![],
+    };
+    let leaf = gc.alloc(leaf);
+    let root = Node {
+        value: gc.alloc(4u32),
+        children: vec![leaf],
+    };
+    let root = gc.alloc(root);
+    let boxed = Boxed { value: gc.alloc(5u32) };
+    let boxed = gc.alloc(boxed);
+
+    gc.collect();
+    assert_eq!(gc.len(), 10);
+    assert_eq!(*pair.first, 1);
+    assert_eq!(*tagged.value, 2);
+    assert_eq!(*root.children[0].value, 3);
+    assert_eq!(*boxed.value, 5);
+}
+
+#[test]
+fn test_derive_raw_identifiers() {
+    #[derive(Scan)]
+    struct Token {
+        r#type: Gc<String>,
+        r#ref: Option<Gc<String>>,
+    }
+
+    let mut gc = Collector::new();
+    let token = Token {
+        r#type: gc.alloc("identifier".to_string()),
+        r#ref: None,
+    };
+    let token = gc.alloc(token);
+
+    gc.collect();
+    assert_eq!(gc.len(), 2);
+    assert_eq!(*token.r#type, "identifier");
+}
+
+#[test]
+fn test_derive_field_attributes() {
+    #[derive(Scan)]
+    struct Script {
+        source: Gc<String>,
+        #[scan(skip)]
+        file: Option<File>,
+        /// Kept alive by `source`.
+        #[scan(unsafe_ignore)]
+        alias: Option<Gc<String>>,
+    }
+
+    let mut gc = Collector::new();
+    let source = gc.alloc("print 1;".to_string());
+    let script = gc.alloc(Script {
+        source: source.clone(),
+        file: None,
+        alias: Some(source),
+    });
+
+    gc.collect();
+    assert_eq!(gc.len(), 2);
+    assert_eq!(script.alias.as_deref().map(String::as_str), Some("print 1;"));
+
+    // The ignored pointer was never unrooted, so it keeps the source alive
+    // until the script is deallocated.
+    drop(script);
+    gc.collect();
+    assert_eq!(gc.len(), 1);
+    gc.collect();
+    assert!(gc.is_empty());
+}
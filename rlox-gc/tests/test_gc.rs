@@ -1,7 +1,14 @@
-#![allow(clippy::blacklisted_name)]
-use rlox_gc::{context::Context, scan::Scan, Collector, Gc};
+#![allow(clippy::disallowed_names)]
+use rlox_gc::{
+    context::Context,
+    scan::{Finalize, Scan},
+    AllocError, CollectStats, Collector, CollectorConfig, Gc, GcCell,
+};
 use rlox_gc_derive::Scan;
-use std::cell::Cell;
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
 #[derive(Debug, Scan)]
 struct Foo {
@@ -120,3 +127,489 @@ fn test_gc_rooting() {
     // println!("{:?}", foo_3);
     drop(foo_3);
 }
+
+/// Host resource that records when it is closed.
+struct Handle {
+    name: &'static str,
+    closed: Rc<RefCell<Vec<String>>>,
+    parent: Option<Gc<Handle>>,
+}
+
+unsafe impl Scan for Handle {
+    fn scan(&self, ctx: &mut Context) {
+        self.parent.scan(ctx);
+    }
+
+    fn root(&self) {
+        self.parent.root();
+    }
+
+    fn unroot(&self) {
+        self.parent.unroot();
+    }
+
+    fn as_finalize(&self) -> Option<&dyn Finalize> {
+        Some(self)
+    }
+}
+
+impl Finalize for Handle {
+    fn finalize(&self) {
+        // The parent must still be readable, even when it's unreachable too.
+        let entry = match &self.parent {
+            Some(parent) => format!("{} ({})", self.name, parent.name),
+            None => self.name.to_string(),
+        };
+        self.closed.borrow_mut().push(entry);
+    }
+}
+
+#[test]
+fn test_finalize() {
+    let mut gc = Collector::new();
+    let closed = Rc::new(RefCell::new(vec![]));
+
+    let a = gc.alloc(Handle {
+        name: "a",
+        closed: closed.clone(),
+        parent: None,
+    });
+    let b = gc.alloc(Handle {
+        name: "b",
+        closed: closed.clone(),
+        parent: Some(a.clone()),
+    });
+    let c = gc.alloc(Handle {
+        name: "c",
+        closed: closed.clone(),
+        parent: None,
+    });
+
+    // Reachable objects are not finalized.
+    gc.collect();
+    assert!(closed.borrow().is_empty());
+    assert_eq!(gc.len(), 3);
+
+    // Unreachable objects are finalized in allocation order, and
+    // kept alive until the next cycle.
+    drop(a);
+    drop(b);
+    gc.collect();
+    assert_eq!(*closed.borrow(), vec!["a".to_string(), "b (a)".to_string()]);
+    assert_eq!(gc.len(), 3);
+
+    // Finalized objects are deallocated without running the finalizer again.
+    gc.collect();
+    assert_eq!(closed.borrow().len(), 2);
+    assert_eq!(gc.len(), 1);
+
+    // Dropping the collector finalizes the rest.
+    drop(c);
+    drop(gc);
+    assert_eq!(closed.borrow().last().map(String::as_str), Some("c"));
+}
+
+#[test]
+fn test_finalize_order() {
+    let mut gc = Collector::new();
+    let closed = Rc::new(RefCell::new(vec![]));
+    let handle = |name| Handle {
+        name,
+        closed: closed.clone(),
+        parent: None,
+    };
+
+    // Free the first slot, so the newer object is placed before the older one.
+    drop(gc.alloc(handle("x")));
+    let a = gc.alloc(handle("a"));
+    gc.collect();
+    gc.collect();
+    let b = gc.alloc(handle("b"));
+    closed.borrow_mut().clear();
+
+    drop(b);
+    drop(a);
+    gc.collect();
+    assert_eq!(*closed.borrow(), vec!["a".to_string(), "b".to_string()]);
+}
+
+#[test]
+fn test_gc_drop_while_panicking() {
+    let closed = Rc::new(RefCell::new(vec![]));
+    let handle = Handle {
+        name: "a",
+        closed: closed.clone(),
+        parent: None,
+    };
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut gc = Collector::new();
+        let _a = gc.alloc(handle);
+        panic!("unwinding");
+    }));
+    assert!(result.is_err());
+
+    // The heap was leaked, so the handle was neither finalized nor dropped.
+    assert!(closed.borrow().is_empty());
+    assert_eq!(Rc::strong_count(&closed), 2);
+}
+
+/// Value that counts how often it's rooted.
+struct Counted(Rc<Cell<usize>>);
+
+unsafe impl Scan for Counted {
+    fn scan(&self, _ctx: &mut Context) {}
+
+    fn root(&self) {
+        self.0.set(self.0.get() + 1);
+    }
+
+    fn unroot(&self) {}
+}
+
+#[test]
+fn test_gc_cell_borrow_roots_once() {
+    let mut gc = Collector::new();
+    let roots = Rc::new(Cell::new(0));
+    let cell = gc.alloc(GcCell::new(vec![Counted(roots.clone())]));
+
+    // Only the first borrow after a cycle roots the contents.
+    for _ in 0..3 {
+        Gc::borrow_mut(&cell, &gc).push(Counted(roots.clone()));
+    }
+    assert_eq!(roots.get(), 1);
+
+    gc.collect();
+    roots.set(0);
+    Gc::borrow_mut(&cell, &gc);
+    assert_eq!(roots.get(), 4);
+
+    // A pointer moved out by a later borrow is still a root.
+    let holder = gc.alloc(GcCell::new(Vec::<Gc<u32>>::new()));
+    let value = gc.alloc(7u32);
+    Gc::borrow_mut(&holder, &gc).push(value);
+    gc.collect();
+    assert!(!Gc::is_root(&holder.borrow()[0]));
+
+    Gc::borrow_mut(&holder, &gc).reserve(1);
+    let value = Gc::borrow_mut(&holder, &gc).pop().unwrap();
+    gc.collect();
+    assert!(Gc::is_root(&value));
+    assert_eq!(*value, 7);
+    assert_eq!(gc.len(), 3);
+}
+
+/// Objects of different sizes are packed into different size classes, and
+/// objects too large for a size class are allocated on their own.
+#[test]
+fn test_gc_size_classes() {
+    let mut gc = Collector::new();
+
+    let small = gc.alloc(1u8);
+    let medium = gc.alloc([7u64; 32]);
+    let large = gc.alloc([[9u64; 32]; 8]);
+    for i in 0..10000u32 {
+        gc.alloc(i);
+    }
+    assert_eq!(gc.len(), 10003);
+
+    gc.collect();
+    assert_eq!(gc.len(), 3);
+    assert_eq!(*small, 1);
+    assert_eq!(medium[31], 7);
+    assert_eq!(large[7][31], 9);
+
+    // Freed slots are reused.
+    let reused = gc.alloc(42u32);
+    assert_eq!(*reused, 42);
+    assert_eq!(gc.len(), 4);
+
+    drop((small, medium, large, reused));
+    gc.collect();
+    assert!(gc.is_empty());
+}
+
+/// Large objects are freed one at a time by a minor collection, which
+/// moves the remaining ones around in the arena's index.
+#[test]
+fn test_gc_free_large() {
+    let mut gc = Collector::new();
+
+    let objects = (0..100u64).map(|i| gc.alloc([[i; 32]; 8])).collect::<Vec<_>>();
+    let kept = objects.into_iter().step_by(3).collect::<Vec<_>>();
+    gc.collect_minor();
+    assert_eq!(gc.len(), kept.len());
+
+    for (i, object) in kept.iter().enumerate() {
+        assert!(gc.contains(object));
+        assert_eq!(object[7][31], i as u64 * 3);
+    }
+
+    drop(kept);
+    gc.collect_minor();
+    assert!(gc.is_empty());
+}
+
+#[test]
+fn test_gc_generations() {
+    let mut gc = Collector::with_config(CollectorConfig {
+        promote_after: 1,
+        ..CollectorConfig::default()
+    });
+
+    // Surviving a single cycle promotes the holder to the old generation.
+    let holder = gc.alloc(GcCell::new(Vec::<Gc<u32>>::new()));
+    gc.collect_minor();
+    assert_eq!(gc.len(), 1);
+    assert_eq!(gc.young_len(), 0);
+
+    // Temporaries die young.
+    for i in 0..100u32 {
+        gc.alloc(i);
+    }
+    gc.collect_minor();
+    assert_eq!(gc.len(), 1);
+
+    // A young object only reachable from an old object is kept alive by the write barrier.
+    let value = gc.alloc(7u32);
+    Gc::borrow_mut(&holder, &gc).push(value);
+    gc.collect_minor();
+    assert_eq!(gc.len(), 2);
+    assert_eq!(*holder.borrow()[0], 7);
+
+    // Moving a pointer out of the cell makes it a root again.
+    let value = Gc::borrow_mut(&holder, &gc).pop().unwrap();
+    assert!(Gc::is_root(&value));
+    drop(value);
+
+    // Old objects are only collected by a full collection.
+    drop(holder);
+    gc.collect_minor();
+    assert_eq!(gc.len(), 2);
+    gc.collect();
+    assert!(gc.is_empty());
+
+    assert_eq!(
+        *gc.stats(),
+        CollectStats {
+            minor_collections: 4,
+            full_collections: 1,
+            minor_freed: 100,
+            full_freed: 2,
+            promoted: 2,
+        }
+    );
+}
+
+/// A full collection must keep remembering old objects that still point to young objects.
+#[test]
+fn test_gc_remembered_after_full() {
+    let mut gc = Collector::with_config(CollectorConfig {
+        promote_after: 2,
+        ..CollectorConfig::default()
+    });
+
+    let holder = gc.alloc(GcCell::new(Vec::<Gc<String>>::new()));
+    gc.collect_minor();
+    gc.collect_minor();
+    assert_eq!(gc.young_len(), 0);
+
+    let value = gc.alloc("young".to_string());
+    Gc::borrow_mut(&holder, &gc).push(value);
+
+    // The value survives the full collection, but is still young afterwards.
+    gc.collect();
+    assert_eq!(gc.young_len(), 1);
+    gc.collect_minor();
+    assert_eq!(gc.len(), 2);
+    assert_eq!(*holder.borrow()[0], "young");
+}
+
+/// An object promoted while pointing to younger objects must be remembered.
+#[test]
+fn test_gc_remembered_after_promotion() {
+    let mut gc = Collector::with_config(CollectorConfig {
+        promote_after: 2,
+        ..CollectorConfig::default()
+    });
+
+    let holder = gc.alloc(GcCell::new(Vec::<Gc<String>>::new()));
+    gc.collect_minor();
+
+    // The holder is still young, so the write isn't remembered.
+    let value = gc.alloc("young".to_string());
+    Gc::borrow_mut(&holder, &gc).push(value);
+
+    // The holder is promoted, while the value it points to stays young.
+    gc.collect_minor();
+    assert_eq!(gc.young_len(), 1);
+    gc.collect_minor();
+    assert_eq!(gc.len(), 2);
+    assert_eq!(*holder.borrow()[0], "young");
+}
+
+#[test]
+fn test_gc_snapshot() {
+    let mut gc = Collector::new();
+
+    let child = gc.alloc(1u32);
+    let parent = gc.alloc(vec![child.clone()]);
+    let before = gc.snapshot();
+
+    assert_eq!(before.objects.len(), 2);
+    let parent_obj = before
+        .objects
+        .iter()
+        .find(|obj| obj.roots == 1 && !obj.edges.is_empty());
+    let parent_obj = parent_obj.expect("parent in snapshot");
+    assert_eq!(parent_obj.type_name, std::any::type_name::<Vec<Gc<u32>>>());
+    assert_eq!(parent_obj.size, Gc::inner_size(&parent));
+    assert_eq!(before.get(parent_obj.edges[0]).unwrap().type_name, "u32");
+    assert!(before
+        .to_dot()
+        .contains(&format!("n{} -> n{};", parent_obj.id, parent_obj.edges[0])));
+
+    let strings = (0..3).map(|_| gc.alloc(String::from("leak"))).collect::<Vec<_>>();
+    drop(parent);
+    gc.collect();
+    let after = gc.snapshot();
+
+    let diff = before.diff(&after);
+    assert_eq!(diff.added.len(), 3);
+    assert_eq!(diff.removed.len(), 1);
+    assert_eq!(diff.by_type()[std::any::type_name::<String>()].count, 3);
+
+    drop((child, strings));
+}
+
+/// A freed object and a later object reusing its memory are different objects.
+#[test]
+fn test_gc_snapshot_reuse() {
+    let mut gc = Collector::new();
+
+    drop(gc.alloc(1u32));
+    gc.collect();
+    let before = gc.snapshot();
+    let first = gc.alloc(2u32);
+    let after_first = gc.snapshot();
+
+    drop(first);
+    gc.collect();
+    let second = gc.alloc(3u32);
+    let after_second = gc.snapshot();
+
+    // Ids follow allocation order, even though the memory is reused.
+    assert!(before.objects.is_empty());
+    assert_eq!(after_first.objects[0].id, 2);
+    assert_eq!(after_second.objects[0].id, 3);
+
+    let diff = after_first.diff(&after_second);
+    assert_eq!(diff.added.len(), 1);
+    assert_eq!(diff.removed.len(), 1);
+
+    drop(second);
+}
+
+#[test]
+fn test_gc_separate_heaps() {
+    let mut first = Collector::new();
+    let mut second = Collector::new();
+
+    let one = first.alloc(1u32);
+    let a = first.alloc(GcCell::new(vec![one]));
+    let two = second.alloc(2u32);
+    let b = second.alloc(GcCell::new(vec![two]));
+    assert!(first.contains(&a));
+    assert!(!first.contains(&b));
+    assert!(second.contains(&b));
+
+    // Each collector only sees its own objects.
+    let c = first.alloc(3u32);
+    Gc::borrow_mut(&a, &first).push(c);
+    drop(b);
+    second.collect();
+    first.collect();
+    assert_eq!(first.len(), 3);
+    assert!(second.is_empty());
+
+    drop(second);
+    assert_eq!(*a.borrow()[1], 3);
+}
+
+#[test]
+#[should_panic(expected = "Gc pointer belongs to a different collector")]
+fn test_gc_cross_heap_alloc() {
+    let mut first = Collector::new();
+    let mut second = Collector::new();
+
+    let value = first.alloc(1u32);
+    second.alloc(vec![value]);
+}
+
+/// Stores are only checked in debug builds, since the check walks the whole cell.
+#[cfg(any(debug_assertions, feature = "verify-heap"))]
+#[test]
+#[should_panic(expected = "Gc pointer belongs to a different collector")]
+fn test_gc_cross_heap_store() {
+    let mut first = Collector::new();
+    let mut second = Collector::new();
+
+    let value = first.alloc(1u32);
+    let holder = second.alloc(GcCell::new(vec![]));
+    Gc::borrow_mut(&holder, &second).push(value);
+}
+
+/// The collector passed in must be the one owning the cell, or the store
+/// would be checked against the wrong heap.
+#[test]
+#[should_panic(expected = "GcCell belongs to a different collector")]
+fn test_gc_cross_heap_borrow() {
+    let mut first = Collector::new();
+    let mut second = Collector::new();
+
+    let value = first.alloc(1u32);
+    let holder = second.alloc(GcCell::new(vec![]));
+    Gc::borrow_mut(&holder, &first).push(value);
+}
+
+#[test]
+fn test_gc_max_heap_bytes() {
+    // The first allocation reserves a whole page.
+    let page = {
+        let mut gc = Collector::new();
+        gc.alloc(0u64);
+        gc.heap_bytes()
+    };
+    let mut gc = Collector::with_config(CollectorConfig {
+        max_heap_bytes: Some(page),
+        ..CollectorConfig::default()
+    });
+
+    let values = (0u64..).map_while(|i| gc.try_alloc(i).ok()).collect::<Vec<_>>();
+    assert!(values.len() > 1);
+    assert_eq!(gc.len(), values.len());
+    assert_eq!(gc.heap_bytes(), page);
+    assert_eq!(gc.stats().full_collections, 1);
+
+    // Nothing can be freed, so the allocation fails.
+    assert_eq!(
+        gc.try_alloc(0u64).unwrap_err(),
+        AllocError {
+            size: page,
+            heap_bytes: page,
+            limit: page,
+        }
+    );
+
+    // Objects too large for a page are charged their own size.
+    let large = gc.try_alloc([0u8; 2048]).unwrap_err();
+    assert!(large.size > 2048);
+
+    // Garbage is collected to make room, and the freed slots are reused.
+    drop(values);
+    let value = gc.try_alloc(3u64).unwrap();
+    assert_eq!(*value, 3);
+    assert_eq!(gc.len(), 1);
+    assert_eq!(gc.heap_bytes(), page);
+    assert_eq!(gc.stats().full_collections, 4);
+}
@@ -0,0 +1,179 @@
+use rlox_gc::{context::Context, scan::Scan, Collector, Gc};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashSet, VecDeque},
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+/// Pointer that is compared by key, so it can be stored in sets.
+struct Keyed(u32, Gc<u32>);
+
+unsafe impl Scan for Keyed {
+    fn scan(&self, ctx: &mut Context) {
+        self.1.scan(ctx);
+    }
+
+    fn root(&self) {
+        self.1.root();
+    }
+
+    fn unroot(&self) {
+        self.1.unroot();
+    }
+}
+
+impl PartialEq for Keyed {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for Keyed {}
+
+impl PartialOrd for Keyed {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Keyed {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl Hash for Keyed {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+/// Moves a pointer into a container allocated in the collector, and checks
+/// that the container unroots it on allocation, and keeps it alive by scanning it.
+fn assert_scans<T, F>(make: F)
+where
+    T: Scan + 'static,
+    F: FnOnce(Gc<u32>) -> T,
+{
+    let mut gc = Collector::new();
+    let child = gc.alloc(42u32);
+    let parent = gc.alloc(make(child));
+
+    // Only reachable through the parent.
+    gc.collect();
+    assert_eq!(gc.len(), 2);
+
+    drop(parent);
+    gc.collect();
+    assert_eq!(gc.len(), 0);
+}
+
+/// Values that hold no pointers can be allocated and collected.
+fn assert_static<T: Scan + 'static>(value: T) {
+    let mut gc = Collector::new();
+    let value = gc.alloc(value);
+    gc.collect();
+    assert_eq!(gc.len(), 1);
+
+    drop(value);
+    gc.collect();
+    assert_eq!(gc.len(), 0);
+}
+
+#[test]
+fn test_scan_static() {
+    assert_static('x');
+    assert_static(7usize);
+    assert_static(-7isize);
+    assert_static("lox");
+}
+
+#[test]
+fn test_scan_box() {
+    assert_scans(Box::new);
+    assert_scans(|child| Box::new(child) as Box<dyn Scan>);
+}
+
+#[test]
+fn test_scan_slice() {
+    assert_scans(|child| vec![child].into_boxed_slice());
+}
+
+#[test]
+fn test_scan_collections() {
+    assert_scans(|child| VecDeque::from(vec![child]));
+    assert_scans(|child| {
+        let mut map = BTreeMap::new();
+        map.insert(1, child);
+        map
+    });
+    assert_scans(|child| Some(Keyed(1, child)).into_iter().collect::<BTreeSet<_>>());
+    assert_scans(|child| Some(Keyed(1, child)).into_iter().collect::<HashSet<_>>());
+}
+
+#[test]
+fn test_scan_tuples() {
+    assert_scans(|child| (child,));
+    assert_scans(|child| (1, child, 'c'));
+    assert_scans(|child| {
+        (
+            0u8, 1u16, 2u32, 3u64, 4usize, 5i8, 6i16, 7i32, 8i64, 9isize, 10.0f32, child,
+        )
+    });
+}
+
+#[test]
+fn test_scan_result() {
+    assert_scans(Ok::<_, ()>);
+    assert_scans(Err::<(), _>);
+}
+
+#[test]
+fn test_scan_arrays() {
+    assert_scans(|child| [child]);
+    assert_scans(|child| {
+        let mut array: [Option<Gc<u32>>; 64] = [(); 64].map(|_| None);
+        array[63] = Some(child);
+        array
+    });
+}
+
+#[test]
+fn test_scan_dyn() {
+    assert_scans(|child| vec![Gc::into_dyn(child)]);
+
+    let mut gc = Collector::new();
+    let number = gc.alloc(1u32);
+    let text = gc.alloc("two".to_string());
+    let objects: Vec<Gc<dyn Scan>> = vec![Gc::into_dyn(number), Gc::into_dyn(text)];
+    assert!(objects.iter().all(Gc::is_root));
+
+    let objects = gc.alloc(objects);
+    gc.collect();
+    assert_eq!(gc.len(), 3);
+
+    drop(objects);
+    gc.collect();
+    assert_eq!(gc.len(), 0);
+}
+
+#[test]
+fn test_scan_rc() {
+    let mut gc = Collector::new();
+    let child = gc.alloc(42u32);
+    let shared = Rc::new(child);
+    let parent = gc.alloc(shared.clone());
+
+    // Contents of an `Rc` stay rooted, since they may be shared outside the collector.
+    assert!(Gc::is_root(&shared));
+
+    drop(parent);
+    gc.collect();
+    assert_eq!(gc.len(), 1);
+    assert_eq!(**shared, 42);
+
+    drop(shared);
+    gc.collect();
+    assert_eq!(gc.len(), 0);
+}
@@ -0,0 +1,137 @@
+#![cfg(feature = "verify-heap")]
+
+use rlox_gc::{context::Context, scan::Scan, Collector, Gc, GcCell};
+
+/// Broken `Scan` implementation that forgets to scan its child.
+struct Forgetful {
+    child: Gc<u32>,
+}
+
+unsafe impl Scan for Forgetful {
+    fn scan(&self, _ctx: &mut Context) {}
+
+    fn root(&self) {
+        self.child.root();
+    }
+
+    fn unroot(&self) {
+        self.child.unroot();
+    }
+}
+
+#[test]
+#[should_panic(expected = "visits different pointers in `scan` and `root`")]
+fn test_verify_forgotten_field() {
+    let mut gc = Collector::new();
+    let child = gc.alloc(1u32);
+    let _parent = gc.alloc(Forgetful { child });
+    gc.collect();
+}
+
+/// Broken `Scan` implementation that only remembers its child when unrooting.
+struct HalfForgotten {
+    child: Gc<u32>,
+}
+
+unsafe impl Scan for HalfForgotten {
+    fn scan(&self, _ctx: &mut Context) {}
+
+    fn root(&self) {}
+
+    fn unroot(&self) {
+        self.child.unroot();
+    }
+}
+
+#[test]
+#[should_panic(expected = "visits different pointers in `scan` and `unroot`")]
+fn test_verify_field_only_unrooted() {
+    let mut gc = Collector::new();
+    let child = gc.alloc(1u32);
+    let _parent = gc.alloc(HalfForgotten { child });
+    gc.collect();
+}
+
+/// Broken `Scan` implementation that unroots its child twice.
+struct DoubleUnroot {
+    child: Gc<u32>,
+}
+
+unsafe impl Scan for DoubleUnroot {
+    fn scan(&self, ctx: &mut Context) {
+        self.child.scan(ctx);
+    }
+
+    fn root(&self) {
+        self.child.root();
+    }
+
+    fn unroot(&self) {
+        self.child.unroot();
+        self.child.unroot();
+    }
+}
+
+#[test]
+#[should_panic(expected = "Use of a deallocated GcBox")]
+fn test_verify_drop_dangling() {
+    let mut gc = Collector::new();
+    let child = gc.alloc(1u32);
+    drop(gc.alloc(DoubleUnroot { child: child.clone() }));
+
+    // The child is no longer rooted by the handle, so it's freed while still in use.
+    gc.collect();
+    drop(child);
+}
+
+#[test]
+fn test_verify_valid_heap() {
+    let mut gc = Collector::new();
+    let child = gc.alloc(1u32);
+    let parent = gc.alloc(GcCell::new(vec![child]));
+
+    gc.collect_minor();
+    gc.collect_minor();
+    let other = gc.alloc(2u32);
+    Gc::borrow_mut(&parent, &gc).push(other);
+    gc.collect_minor();
+    gc.collect();
+
+    assert_eq!(gc.len(), 3);
+    drop(parent);
+}
+
+#[test]
+fn test_unreachable_roots() {
+    let mut gc = Collector::new();
+    let child = gc.alloc(1u32);
+    let stack = vec![gc.alloc(vec![child])];
+    let leaked = gc.alloc(2u32);
+
+    let unreachable = gc.unreachable_roots(&stack);
+    assert_eq!(unreachable.len(), 1);
+    assert_eq!(unreachable[0].size, Gc::inner_size(&leaked));
+    assert_eq!(unreachable[0].type_name, "u32");
+    assert_eq!(unreachable[0].roots, 1);
+
+    // The heap is left untouched.
+    gc.collect();
+    assert_eq!(gc.len(), 3);
+}
+
+/// Pointers in an `Rc` are scanned but stay rooted, which the verifier accepts.
+#[test]
+fn test_verify_scan_only_rc() {
+    let mut gc = Collector::new();
+    let child = gc.alloc(1u32);
+    let parent = gc.alloc(std::rc::Rc::new(child));
+    gc.collect();
+    assert_eq!(gc.len(), 2);
+
+    // The child stays rooted until the `Rc` is dropped along with its holder.
+    drop(parent);
+    gc.collect();
+    assert_eq!(gc.len(), 1);
+    gc.collect();
+    assert!(gc.is_empty());
+}